@@ -2,7 +2,7 @@ use color_eyre::eyre::Result as Res;
 
 use crate::{
     cli::Cli,
-    core::{config::Config, runner::Runner},
+    core::{config::ConfigStatus, runner::Runner},
 };
 
 #[derive(Debug)]
@@ -12,11 +12,9 @@ pub struct App {
 }
 
 impl App {
-    pub fn new(args: Cli) -> Res<Self> {
-        let config_status = Config::parse()?;
-
+    pub fn new(args: Cli, config_status: ConfigStatus) -> Res<Self> {
         match config_status {
-            crate::core::config::ConfigStatus::Loaded(_) => tracing::info!("loaded config"),
+            ConfigStatus::Loaded(..) => tracing::info!("loaded config"),
             _ => tracing::info!("loaded config from default"),
         }
         let config = config_status.config();
@@ -27,16 +25,38 @@ impl App {
         })
     }
 
+    /// Whether any file operation failed, the process then exits with 1
+    pub fn failed(&self) -> bool {
+        self.runner.failed()
+    }
+
     pub async fn run(&mut self) -> Res<()> {
         crossterm::execute!(
             std::io::stdout(),
-            crossterm::cursor::SetCursorStyle::BlinkingBlock // 或者 BlinkingBar, BlinkingUnderline
+            crossterm::cursor::SetCursorStyle::BlinkingBlock, // 或者 BlinkingBar, BlinkingUnderline
+            // 粘贴作为一个 Msg::Paste 到达，而不是逐个按键
+            crossterm::event::EnableBracketedPaste
         )?;
         color_eyre::install()?;
 
         tracing::info!("[App] app started");
 
-        self.runner.run(&mut self.term).await?;
+        let res = self.runner.run(&mut self.term).await;
+        crossterm::execute!(std::io::stdout(), crossterm::event::DisableBracketedPaste)?;
+        res?;
+
+        if let Some(report) = self.runner.dry_run_report() {
+            if report.is_empty() {
+                println!("Dry run, nothing would be changed.");
+            } else {
+                println!("Dry run, nothing was changed. Would have done:");
+                print!("{}", report);
+            }
+        }
+
+        if let Some(path) = self.runner.saved_plan() {
+            println!("Plan saved to '{}', see `ef plan export`.", path.display());
+        }
 
         tracing::info!("[App] app ended");
         Ok(())
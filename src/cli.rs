@@ -1,14 +1,31 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use color_eyre::eyre::Result as Res;
+
+use crate::core::{
+    cmd::Cmd,
+    config::Config,
+    file_ops::ConflictPolicy,
+    journal::{FsOp, Journal},
+    plan::{Plan, PlanFormat},
+    report::{DryRunReport, Outcome},
+};
 
 #[derive(Parser)]
 #[command(version, about, long_about = None,)]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Optional path to open
     #[arg(short, long, value_name = "PATH")]
     pub path: Option<String>,
 
+    /// Directory to open, same as `--path`
+    #[arg(value_name = "DIR", conflicts_with = "path")]
+    pub dir: Option<String>,
+
     /// Sets a custom config file
     #[arg(short, long, value_name = "FILE")]
     pub config: Option<PathBuf>,
@@ -28,4 +45,239 @@ pub struct Cli {
     /// Use verbose output
     #[arg(short = 'v', long = "verbose")]
     pub verbose: bool,
+
+    /// Write the log to this file instead of the rolling logs in the state dir
+    #[arg(long, value_name = "FILE")]
+    pub log_file: Option<PathBuf>,
+}
+
+impl Cli {
+    /// The directory given on the command line, either positional or with `--path`
+    pub fn open_path(&self) -> Option<&str> {
+        self.path.as_deref().or(self.dir.as_deref())
+    }
+
+    /// The log filter directive. The flags win over `EF_LOG`, which wins over `RUST_LOG`:
+    /// `-q` is off, `-v` or `-d` is debug, `-dd` is trace, the default is info.
+    pub fn log_directive(&self, env: impl Fn(&str) -> Option<String>) -> String {
+        let flag = if self.quiet {
+            Some("off")
+        } else if self.debug >= 2 {
+            Some("trace")
+        } else if self.debug == 1 || self.verbose {
+            Some("debug")
+        } else {
+            None
+        };
+        let var = |key| env(key).filter(|s| !s.trim().is_empty());
+        flag.map(str::to_string)
+            .or_else(|| var("EF_LOG"))
+            .or_else(|| var("RUST_LOG"))
+            .unwrap_or_else(|| "info".to_string())
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Undo the last batch of file operations performed by ef
+    Undo,
+    /// Work with sort plans without the TUI
+    Plan {
+        #[command(subcommand)]
+        command: PlanCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PlanCommand {
+    /// Write the plan saved with Ctrl+S in the TUI for the opened directory,
+    /// or all of its items pending if there is none
+    Export {
+        /// Write to this file instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+        /// Defaults to the extension of the output, else toml
+        #[arg(long)]
+        format: Option<PlanFormat>,
+    },
+    /// Validate a plan file and perform it
+    Apply {
+        #[arg(value_name = "PLAN")]
+        file: PathBuf,
+    },
+}
+
+/// `ef plan export`
+pub fn plan_export(config: &Config, output: Option<&Path>, format: Option<PlanFormat>) -> Res<()> {
+    let root = match &config.default_path {
+        Some(path) => path.clone(),
+        None => std::env::current_dir()?,
+    };
+    let plan = export_plan(
+        &root,
+        Plan::default_path().as_deref(),
+        config.show_hidden,
+        config.respect_gitignore,
+    )?;
+    let format = format
+        .or(output.map(PlanFormat::from_path))
+        .unwrap_or(PlanFormat::Toml);
+    match output {
+        Some(path) => plan.save(path, format)?,
+        None => print!("{}", plan.to_string(format)?),
+    }
+    Ok(())
+}
+
+/// The plan the TUI saved at `saved` if it is for `root`, else every item of `root` pending.
+/// Says on stderr which one it is, stdout may be the plan itself.
+fn export_plan(
+    root: &Path,
+    saved: Option<&Path>,
+    show_hidden: bool,
+    respect_gitignore: bool,
+) -> Res<Plan> {
+    if let Some(saved) = saved.filter(|path| path.exists()) {
+        let plan = Plan::parse_from_path(saved)?;
+        if plan.root.canonicalize().ok() == root.canonicalize().ok() {
+            eprintln!(
+                "Exporting the plan saved by the TUI at '{}'",
+                saved.display()
+            );
+            return Ok(plan);
+        }
+        eprintln!(
+            "The plan saved by the TUI at '{}' is for '{}', listing the directory instead",
+            saved.display(),
+            plan.root.display()
+        );
+    }
+    Plan::from_dir(root, show_hidden, respect_gitignore)
+}
+
+/// `ef plan apply`, prints every operation performed and every planned one that was not.
+/// `policy` is used by the sides of the plan without `on_conflict`
+pub fn plan_apply(file: &Path, policy: ConflictPolicy, dry_run: bool) -> Res<()> {
+    let cmds = Plan::parse_from_path(file)?.to_cmds(policy)?;
+    if cmds.is_empty() {
+        println!("Nothing to do.");
+        return Ok(());
+    }
+
+    if dry_run {
+        print!("{}", DryRunReport::of(&Cmd::Seq(cmds)));
+        return Ok(());
+    }
+
+    let journal = Journal::open_default();
+    let batch = Journal::new_batch();
+    for cmd in cmds {
+        let ops = FsOp::plan(&cmd);
+        let outcome = Outcome::execute(cmd, &ops, None, None);
+        outcome.done.iter().for_each(|op| println!("done: {}", op));
+        outcome
+            .not_done
+            .iter()
+            .for_each(|op| println!("not done: {}", op));
+        if let Some(journal) = &journal {
+            journal.record(batch, outcome.done.clone())?;
+        }
+        outcome.result().map_err(|e| color_eyre::eyre::eyre!(e))?;
+    }
+    Ok(())
+}
+
+/// `ef undo`, prints what was undone
+pub fn undo(dry_run: bool) -> Res<()> {
+    let journal = Journal::open_default()
+        .ok_or_else(|| color_eyre::eyre::eyre!("Could not determine the state directory"))?;
+
+    if dry_run {
+        match journal.last_batch()? {
+            Some((_, ops)) => ops
+                .iter()
+                .rev()
+                .for_each(|op| println!("would undo: {}", op)),
+            None => println!("Nothing to undo."),
+        }
+        return Ok(());
+    }
+
+    let Some(report) = journal.undo_last()? else {
+        println!("Nothing to undo.");
+        return Ok(());
+    };
+    for op in &report.undone {
+        println!("undone: {}", op);
+    }
+    for (op, e) in &report.failed {
+        eprintln!("failed to undo {}: {}", op, e);
+    }
+    if !report.failed.is_empty() {
+        color_eyre::eyre::bail!("{} operation(s) could not be undone", report.failed.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn directive(args: &[&str], env: &[(&str, &str)]) -> String {
+        let cli = Cli::parse_from(std::iter::once("ef").chain(args.iter().copied()));
+        cli.log_directive(|key| {
+            env.iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.to_string())
+        })
+    }
+
+    #[test]
+    fn test_log_directive_flags() {
+        assert_eq!(directive(&[], &[]), "info");
+        assert_eq!(directive(&["-q"], &[]), "off");
+        assert_eq!(directive(&["-v"], &[]), "debug");
+        assert_eq!(directive(&["-d"], &[]), "debug");
+        assert_eq!(directive(&["-dd"], &[]), "trace");
+        assert_eq!(directive(&["-q", "-dd"], &[]), "off");
+    }
+
+    #[test]
+    fn test_export_saved_plan() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path().join("root");
+        std::fs::create_dir(&root)?;
+        std::fs::write(root.join("a.txt"), "a")?;
+        let saved = dir.path().join("plan.toml");
+
+        // nothing saved yet
+        let listed = export_plan(&root, Some(&saved), false, true)?;
+        assert_eq!(listed.pending, [PathBuf::from("a.txt")]);
+
+        let mut plan = listed.clone();
+        plan.left.items = std::mem::take(&mut plan.pending);
+        plan.save(&saved, PlanFormat::Toml)?;
+        assert_eq!(export_plan(&root, Some(&saved), false, true)?, plan);
+
+        // saved for another directory
+        assert_eq!(
+            export_plan(dir.path(), Some(&saved), false, true)?.root,
+            dir.path()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_log_directive_env() {
+        assert_eq!(directive(&[], &[("RUST_LOG", "warn")]), "warn");
+        assert_eq!(
+            directive(&[], &[("RUST_LOG", "warn"), ("EF_LOG", "ef=trace")]),
+            "ef=trace"
+        );
+        assert_eq!(directive(&["-v"], &[("EF_LOG", "error")]), "debug");
+        assert_eq!(
+            directive(&[], &[("EF_LOG", " "), ("RUST_LOG", "warn")]),
+            "warn"
+        );
+    }
 }
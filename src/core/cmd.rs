@@ -1,6 +1,10 @@
 use std::path::PathBuf;
 
-use crate::core::model::{component::ScrollList, selector::SelectModel};
+use crate::core::{
+    file_ops::ConflictPolicy,
+    model::{component::ScrollList, selector::SelectModel},
+    plan::Plan,
+};
 
 #[derive(Debug, Default)]
 pub enum Cmd {
@@ -11,6 +15,8 @@ pub enum Cmd {
     Seq(Vec<Self>),
     /// Not sequential commands
     Batch(Vec<Self>),
+    /// Journal the file operations of the command under this batch, see [`crate::core::journal`]
+    InBatch(u64, Box<Self>),
     IntoProcess(SelectModel),
     IntoSelect(
         PathBuf,
@@ -22,18 +28,56 @@ pub enum Cmd {
     // /// The first argument is a u64 id, which should be internally managed by the model.
     // / The second argument should be the async task to perform.
     // AsyncTask(u64, Box<Self>),
-    AsyncOrganize(u64,Vec<PathBuf>, PathBuf),
+    AsyncOrganize(u64,Vec<PathBuf>, PathBuf, ConflictPolicy),
     AsyncDelete(u64,Vec<PathBuf>),
-    AsyncCopy(u64,Vec<PathBuf>, PathBuf),
+    AsyncCopy(u64,Vec<PathBuf>, PathBuf, ConflictPolicy),
     AsyncTrash(u64, Vec<PathBuf>),
-    AsyncMove(u64, Vec<PathBuf>, PathBuf),
-    Organize(Vec<PathBuf>, PathBuf),
+    AsyncMove(u64, Vec<PathBuf>, PathBuf, ConflictPolicy),
+    /// Pack the items into the archive, the format is chosen by its extension
+    AsyncZip(u64, Vec<PathBuf>, PathBuf),
+    /// Unpack the archive into the directory
+    AsyncExtract(u64, PathBuf, PathBuf),
+    AsyncRename(u64, Vec<(PathBuf, PathBuf)>),
+    /// Gather the items into a new directory, see [`ConflictPolicy`] for existing names
+    Organize(Vec<PathBuf>, PathBuf, ConflictPolicy),
     Delete(Vec<PathBuf>),
-    Copy(Vec<PathBuf>, PathBuf),
+    Copy(Vec<PathBuf>, PathBuf, ConflictPolicy),
     Trash(Vec<PathBuf>),
-    Move(Vec<PathBuf>, PathBuf),
+    /// Move the items into a directory, which may exist
+    Move(Vec<PathBuf>, PathBuf, ConflictPolicy),
+    /// Rename each `(from, to)` pair on disk
+    Rename(Vec<(PathBuf, PathBuf)>),
     Exit,
+    /// Reverse the last batch of file operations in the journal
+    Undo,
+    /// Ask the async task with this id to stop
+    CancelTask(u64),
     ToggleShowHidden,
     ToggleRespectGitIgnore,
+    /// Switch between the flat and the recursive view, see [`crate::core::file_ops::ViewMode`]
+    ToggleRecursive,
     LoadDir(PathBuf),
+    /// Watch this directory instead, once the selector moved there
+    ChangeWatchPath(PathBuf),
+    /// Save what is being sorted, see [`Plan::default_path`]
+    SavePlan(Plan),
+}
+
+impl Cmd {
+    /// The task ids of all `Async*` commands, looking into `Seq`, `Batch` and `InBatch`
+    pub fn task_ids(&self) -> Vec<u64> {
+        match self {
+            Cmd::AsyncOrganize(id, ..)
+            | Cmd::AsyncDelete(id, ..)
+            | Cmd::AsyncCopy(id, ..)
+            | Cmd::AsyncTrash(id, ..)
+            | Cmd::AsyncMove(id, ..)
+            | Cmd::AsyncZip(id, ..)
+            | Cmd::AsyncExtract(id, ..)
+            | Cmd::AsyncRename(id, ..) => vec![*id],
+            Cmd::Seq(cmds) | Cmd::Batch(cmds) => cmds.iter().flat_map(Cmd::task_ids).collect(),
+            Cmd::InBatch(_, cmd) => cmd.task_ids(),
+            _ => vec![],
+        }
+    }
 }
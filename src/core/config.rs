@@ -1,7 +1,15 @@
-use color_eyre::Result as Res;
+use color_eyre::{Result as Res, eyre::Context as _};
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 
+use crate::core::{
+    file_ops::{ConflictPolicy, ViewMode},
+    paths,
+};
+
+/// 每个目录下的局部配置文件名
+pub const LOCAL_CONFIG_NAME: &str = ".ef.toml";
+
 #[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
 pub struct Config {
     /// 帧率，默认为60
@@ -15,12 +23,98 @@ pub struct Config {
     pub show_hidden: bool,
     /// 是否尊重 gitignore
     pub respect_gitignore: bool,
+    /// 最多保留的日志文件数（每小时一个），0 表示全部保留
+    #[serde(default = "default_log_max_files")]
+    pub log_max_files: usize,
+    /// 整理、复制、移动时目标已存在的默认处理方式，可在 Processor 里按侧修改
+    #[serde(default)]
+    pub conflict_policy: ConflictPolicy,
+    /// 是否递归列出子目录里的文件，可按 v 切换
+    #[serde(default)]
+    pub recursive: bool,
+    /// 递归时最多进入几层，1 表示只有当前目录，为空表示不限
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+}
+
+fn default_log_max_files() -> usize {
+    Config::default().log_max_files
+}
+
+/// One config file. Every field is optional, so that a file only overrides what it sets.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct ConfigLayer {
+    pub frame_rate: Option<f64>,
+    pub tick_rate: Option<f64>,
+    pub default_path: Option<PathBuf>,
+    pub show_hidden: Option<bool>,
+    pub respect_gitignore: Option<bool>,
+    pub log_max_files: Option<usize>,
+    pub conflict_policy: Option<ConflictPolicy>,
+    pub recursive: Option<bool>,
+    pub max_depth: Option<usize>,
+}
+
+impl ConfigLayer {
+    pub fn parse_from_str(str: &str) -> Res<Self> {
+        toml::from_str(str).map_err(|e| e.into())
+    }
+
+    pub fn parse_from_path(path: impl AsRef<Path>) -> Res<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config '{}'", path.display()))?;
+        Self::parse_from_str(&content)
+            .with_context(|| format!("Failed to parse config '{}'", path.display()))
+    }
+
+    /// Override the fields of `config` that are set in this layer
+    pub fn apply(self, config: &mut Config) {
+        let Self {
+            frame_rate,
+            tick_rate,
+            default_path,
+            show_hidden,
+            respect_gitignore,
+            log_max_files,
+            conflict_policy,
+            recursive,
+            max_depth,
+        } = self;
+        if let Some(v) = frame_rate {
+            config.frame_rate = v;
+        }
+        if let Some(v) = tick_rate {
+            config.tick_rate = v;
+        }
+        if let Some(v) = default_path {
+            config.default_path = Some(v);
+        }
+        if let Some(v) = show_hidden {
+            config.show_hidden = v;
+        }
+        if let Some(v) = respect_gitignore {
+            config.respect_gitignore = v;
+        }
+        if let Some(v) = log_max_files {
+            config.log_max_files = v;
+        }
+        if let Some(v) = conflict_policy {
+            config.conflict_policy = v;
+        }
+        if let Some(v) = recursive {
+            config.recursive = v;
+        }
+        if let Some(v) = max_depth {
+            config.max_depth = Some(v);
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum ConfigStatus {
-    /// 从配置文件成功加载
-    Loaded(Config),
+    /// 从配置文件成功加载，以及按顺序加载的文件
+    Loaded(Config, Vec<PathBuf>),
     /// 配置文件不存在或无法获取目录，回退到默认值
     Default(Config),
 }
@@ -28,7 +122,30 @@ pub enum ConfigStatus {
 impl ConfigStatus {
     pub fn config(self) -> Config {
         match self {
-            ConfigStatus::Loaded(c) | ConfigStatus::Default(c) => c,
+            ConfigStatus::Loaded(c, _) | ConfigStatus::Default(c) => c,
+        }
+    }
+
+    pub fn config_ref(&self) -> &Config {
+        match self {
+            ConfigStatus::Loaded(c, _) | ConfigStatus::Default(c) => c,
+        }
+    }
+
+    /// The config files applied, in order. Loading happens before logging is set up,
+    /// so they are logged by the caller.
+    pub fn sources(&self) -> &[PathBuf] {
+        match self {
+            ConfigStatus::Loaded(_, sources) => sources,
+            ConfigStatus::Default(_) => &[],
+        }
+    }
+
+    fn new(config: Config, sources: Vec<PathBuf>) -> Self {
+        if sources.is_empty() {
+            ConfigStatus::Default(config)
+        } else {
+            ConfigStatus::Loaded(config, sources)
         }
     }
 }
@@ -37,41 +154,117 @@ impl Config {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// How the directory is listed, see [`crate::core::file_ops::list_items`]
+    pub fn view_mode(&self) -> ViewMode {
+        if self.recursive {
+            ViewMode::Recursive {
+                max_depth: self.max_depth,
+            }
+        } else {
+            ViewMode::Flat
+        }
+    }
+    /// Missing fields fall back to their defaults
     pub fn parse_from_str(str: &str) -> Res<Self> {
-        toml::from_str(str).map_err(|e| e.into())
+        let mut config = Self::default();
+        ConfigLayer::parse_from_str(str)?.apply(&mut config);
+        Ok(config)
     }
 
     /// 新增：从指定路径解析配置，方便测试
     pub fn parse_from_path(path: impl AsRef<Path>) -> Res<Self> {
-        let content = std::fs::read_to_string(path)?;
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config '{}'", path.display()))?;
         Self::parse_from_str(&content)
+            .with_context(|| format!("Failed to parse config '{}'", path.display()))
     }
 
-    /// parse from the default config path
-    /// Err if config content error.
-    pub fn parse() -> Res<ConfigStatus> {
-        use etcetera::app_strategy::{AppStrategy, AppStrategyArgs, Xdg};
+    /// Build the config from every layer, later ones win:
+    /// 1. the defaults
+    /// 2. `custom` if given (it must exist), else the global `$XDG_CONFIG_HOME/ef/config.toml`
+    /// 3. the first [`LOCAL_CONFIG_NAME`] found from the opened directory up to the root
+    /// 4. `open_path` from the command line
+    ///
+    /// The opened directory, `open_path` or `default_path` or the current dir, ends up
+    /// resolved in `default_path`. It can not be changed by a local config.
+    /// Err if a config content is wrong or the directory to open does not exist.
+    pub fn load(custom: Option<&Path>, open_path: Option<&str>) -> Res<ConfigStatus> {
+        let status = Self::load_global(custom)?;
+        let mut sources = status.sources().to_vec();
+        let mut config = status.config();
 
-        // 1. 定义应用策略参数
-        let args = AppStrategyArgs {
-            top_level_domain: "com".to_string(), // 这俩都仅在某些策略中用到，应该不用担心
-            author: "ef_author".to_string(),
-            app_name: "ef".to_string(),
+        // 命令行参数优先于配置文件
+        let open_dir = match open_path {
+            Some(path) => paths::resolve_dir(path)?,
+            None => match &config.default_path {
+                Some(path) => paths::resolve_dir(path)?,
+                None => std::env::current_dir()?,
+            },
         };
 
-        // 2. 显式使用 Xdg 策略（确保在 macOS 上使用 ~/.config/ef）
-        let config_path = Xdg::new(args)
-            .ok()
-            .map(|strategy| strategy.config_dir().join("config.toml"));
-
-        if let Some(path) = config_path.as_ref().filter(|p| p.exists()) {
-            return Ok(ConfigStatus::Loaded(Self::parse_from_path(path)?));
+        if let Some(path) = find_local_config(&open_dir) {
+            let layer = ConfigLayer {
+                default_path: None,
+                ..ConfigLayer::parse_from_path(&path)?
+            };
+            layer.apply(&mut config);
+            sources.push(path);
         }
 
-        Ok(ConfigStatus::Default(Self::new()))
+        config.default_path = Some(open_dir);
+        Ok(ConfigStatus::new(config, sources))
+    }
+
+    /// Only the first two layers of [`Config::load`], for the commands that open no directory.
+    /// `default_path` is left as it is written.
+    pub fn load_global(custom: Option<&Path>) -> Res<ConfigStatus> {
+        let base_path = match custom {
+            Some(path) => Some(path.to_path_buf()),
+            None => global_config_path().filter(|p| p.exists()),
+        };
+        let config = match &base_path {
+            Some(path) => Self::parse_from_path(path)?,
+            None => Self::new(),
+        };
+        Ok(ConfigStatus::new(config, base_path.into_iter().collect()))
     }
 }
 
+/// `$XDG_CONFIG_HOME/ef/config.toml`
+fn global_config_path() -> Option<PathBuf> {
+    use etcetera::app_strategy::AppStrategy;
+    app_strategy().map(|strategy| strategy.config_dir().join("config.toml"))
+}
+
+/// The nearest [`LOCAL_CONFIG_NAME`] in `dir` or one of its ancestors
+fn find_local_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|d| d.join(LOCAL_CONFIG_NAME))
+        .find(|p| p.is_file())
+}
+
+fn app_strategy() -> Option<etcetera::app_strategy::Xdg> {
+    use etcetera::app_strategy::{AppStrategyArgs, Xdg};
+
+    // 1. 定义应用策略参数
+    let args = AppStrategyArgs {
+        top_level_domain: "com".to_string(), // 这俩都仅在某些策略中用到，应该不用担心
+        author: "ef_author".to_string(),
+        app_name: "ef".to_string(),
+    };
+
+    // 2. 显式使用 Xdg 策略（确保在 macOS 上使用 ~/.config/ef）
+    Xdg::new(args).ok()
+}
+
+/// `$XDG_STATE_HOME/ef`, where the logs and the operation journal live
+pub fn state_dir() -> Option<PathBuf> {
+    use etcetera::app_strategy::AppStrategy;
+    app_strategy()?.state_dir()
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -80,6 +273,10 @@ impl Default for Config {
             default_path: None,
             show_hidden: false,
             respect_gitignore: true,
+            log_max_files: 48,
+            conflict_policy: ConflictPolicy::Ask,
+            recursive: false,
+            max_depth: None,
         }
     }
 }
@@ -117,10 +314,93 @@ respect_gitignore = true"#;
             default_path: PathBuf::from("/Users/abc/Desktop/").into(),
             show_hidden: true,
             respect_gitignore: true,
+            log_max_files: 48,
+            conflict_policy: ConflictPolicy::Ask,
+            recursive: false,
+            max_depth: None,
         };
         assert_eq!(expected, config);
     }
 
+    #[test]
+    fn test_config_parse_partial() -> Res<()> {
+        let config = Config::parse_from_str(
+            "show_hidden = true\nlog_max_files = 0\nconflict_policy = \"rename\"\nrecursive = true\nmax_depth = 3",
+        )?;
+        let expected = Config {
+            show_hidden: true,
+            log_max_files: 0,
+            conflict_policy: ConflictPolicy::Rename,
+            recursive: true,
+            max_depth: Some(3),
+            ..Config::new()
+        };
+        assert_eq!(expected, config);
+        assert_eq!(
+            config.view_mode(),
+            ViewMode::Recursive { max_depth: Some(3) }
+        );
+        assert_eq!(Config::new().view_mode(), ViewMode::Flat);
+        assert_eq!(Config::parse_from_str("")?, Config::new());
+        assert!(Config::parse_from_str("show_hidden = 1").is_err());
+        assert!(Config::parse_from_str("conflict_policy = \"burn\"").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_load_layers() -> Res<()> {
+        let root = tempfile::tempdir()?;
+        let root = root.path().canonicalize()?;
+        let project = root.join("project");
+        let opened = project.join("sub");
+        std::fs::create_dir_all(&opened)?;
+
+        let custom = root.join("custom.toml");
+        std::fs::write(
+            &custom,
+            format!(
+                "tick_rate = 8.0\nshow_hidden = true\ndefault_path = {:?}\n",
+                root
+            ),
+        )?;
+        std::fs::write(
+            project.join(LOCAL_CONFIG_NAME),
+            "show_hidden = false\nrespect_gitignore = false\ndefault_path = \"/elsewhere\"\n",
+        )?;
+
+        // default_path of the custom config is opened, no local config above it
+        let config = Config::load(Some(&custom), None)?.config();
+        assert_eq!(config.tick_rate, 8.0);
+        assert!(config.show_hidden);
+        assert!(config.respect_gitignore);
+        assert_eq!(config.default_path, Some(root.clone()));
+
+        // the cli path wins, and the local config of its parent applies
+        let status = Config::load(Some(&custom), opened.to_str())?;
+        assert_eq!(
+            status.sources(),
+            [custom.clone(), project.join(LOCAL_CONFIG_NAME)]
+        );
+        let config = status.config();
+        assert_eq!(config.tick_rate, 8.0);
+        assert!(!config.show_hidden);
+        assert!(!config.respect_gitignore);
+        assert_eq!(config.default_path, Some(opened));
+
+        assert!(Config::load(Some(&root.join("missing.toml")), None).is_err());
+
+        // a missing directory to open only matters to `load`
+        std::fs::write(&custom, "default_path = \"/surely/missing/dir\"\n")?;
+        assert!(Config::load(Some(&custom), None).is_err());
+        let status = Config::load_global(Some(&custom))?;
+        assert_eq!(status.sources(), [custom]);
+        assert_eq!(
+            status.config().default_path,
+            Some(PathBuf::from("/surely/missing/dir"))
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_parse_from_path() -> Res<()> {
         // 使用 tempfile 创建一个临时文件
@@ -1,10 +1,13 @@
-use crate::core::model::component::FileItem;
+use crate::core::{
+    model::component::FileItem,
+    service::tasks::{CANCELLED, CancelToken, ProgressFn},
+};
 use std::collections::{HashSet, VecDeque};
 use std::fs;
-use std::sync::Arc;
 
-use std::io;
-use std::path::Path;
+use std::ffi::OsStr;
+use std::io::{self, Read as _, Write as _};
+use std::path::{Path, PathBuf};
 
 use color_eyre::{
     Result as Res,
@@ -12,18 +15,37 @@ use color_eyre::{
 };
 
 use ignore::WalkBuilder;
+use serde::{Deserialize, Serialize};
+
+/// Which items [`list_items`] lists
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ViewMode {
+    /// the direct children, directories included
+    #[default]
+    Flat,
+    /// only the files of the whole tree, at most `max_depth` levels deep if set,
+    /// named by their path relative to the listed directory
+    Recursive { max_depth: Option<usize> },
+}
 
 /// 根据配置获取目录下的文件列表，并封装为 FileItem
 pub fn list_items(
     path: &Path,
     show_hidden: bool,
     respect_gitignore: bool,
+    view: ViewMode,
 ) -> Res<VecDeque<FileItem>> {
     let mut res = VecDeque::new();
+    let max_depth = match view {
+        ViewMode::Flat => Some(1),
+        ViewMode::Recursive { max_depth } => max_depth,
+    };
     let walker = WalkBuilder::new(path)
         .hidden(!show_hidden)
         .git_ignore(respect_gitignore)
-        .max_depth(Some(1))
+        .max_depth(max_depth)
+        // 递归时同一目录下的文件挨在一起
+        .sort_by_file_name(|a, b| a.cmp(b))
         .build();
 
     let mut id = 0;
@@ -33,12 +55,23 @@ pub fn list_items(
         if entry.depth() == 0 {
             continue;
         }
+        let is_dir = entry.file_type().map(|ft| ft.is_dir()).unwrap_or(false);
+        let display_name = match view {
+            ViewMode::Flat => entry.file_name().to_string_lossy().to_string(),
+            ViewMode::Recursive { .. } if is_dir => continue,
+            ViewMode::Recursive { .. } => entry
+                .path()
+                .strip_prefix(path)
+                .unwrap_or(entry.path())
+                .to_string_lossy()
+                .to_string(),
+        };
 
         let item = FileItem {
             id,
             path: entry.path().to_path_buf(),
-            display_name: entry.file_name().to_string_lossy().to_string(),
-            is_dir: entry.file_type().map(|ft| ft.is_dir()).unwrap_or(false),
+            display_name,
+            is_dir,
         };
         res.push_back(item);
         id += 1;
@@ -72,127 +105,554 @@ pub fn get_filtered_files(path: &Path, respect_gitignore: bool) -> Vec<String> {
     files
 }
 
-pub fn organize<P: AsRef<Path>>(items: &[P], target_dir_path: &Path) -> Res<()> {
-    let dest_dir = target_dir_path;
-    if dest_dir.exists() {
-        bail!(
-            "Destination directory '{}' already exists.",
-            dest_dir.display()
-        );
+/// A reason a file operation would fail, found before anything is touched
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Conflict {
+    DestinationExists(PathBuf),
+    SourceMissing(PathBuf),
+    InvalidName(PathBuf),
+    /// several items would be renamed to this path
+    DuplicateTarget(PathBuf),
+}
+
+impl std::fmt::Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Conflict::DestinationExists(path) => write!(f, "'{}' already exists.", path.display()),
+            Conflict::SourceMissing(path) => {
+                write!(f, "Source item '{}' does not exist.", path.display())
+            }
+            Conflict::InvalidName(path) => write!(f, "Invalid file name: {:?}", path),
+            Conflict::DuplicateTarget(path) => write!(
+                f,
+                "Multiple items would be renamed to '{}'.",
+                path.display()
+            ),
+        }
+    }
+}
+
+/// What to do when an item put into a directory meets an existing one with the same name
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    /// decide before running, see the Processor; elsewhere an existing name is a conflict
+    #[default]
+    Ask,
+    /// an existing destination directory is reused, directories are merged,
+    /// but a file existing on both sides is still a conflict
+    Merge,
+    /// leave the item where it is
+    Skip,
+    /// replace the existing one, which goes to the trash once the new one is in place
+    Overwrite,
+    /// put the item under a free `name (n).ext`
+    Rename,
+}
+
+impl ConflictPolicy {
+    /// The next policy, for cycling through them in the Processor
+    pub fn next(self) -> Self {
+        match self {
+            ConflictPolicy::Ask => ConflictPolicy::Merge,
+            ConflictPolicy::Merge => ConflictPolicy::Skip,
+            ConflictPolicy::Skip => ConflictPolicy::Overwrite,
+            ConflictPolicy::Overwrite => ConflictPolicy::Rename,
+            ConflictPolicy::Rename => ConflictPolicy::Ask,
+        }
     }
+}
 
-    for item_path in items {
-        if !item_path.as_ref().exists() {
-            bail!(
-                "Source item '{}' does not exist.",
-                item_path.as_ref().display()
-            );
+impl std::fmt::Display for ConflictPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConflictPolicy::Ask => write!(f, "ask"),
+            ConflictPolicy::Merge => write!(f, "merge"),
+            ConflictPolicy::Skip => write!(f, "skip"),
+            ConflictPolicy::Overwrite => write!(f, "overwrite"),
+            ConflictPolicy::Rename => write!(f, "rename"),
         }
     }
+}
 
-    let mut seen_names = HashSet::new();
-    for item_path in items {
-        let name = item_path.as_ref().file_name().ok_or_else(|| {
-            color_eyre::eyre::eyre!("Invalid file name: {:?}", item_path.as_ref())
-        })?;
-        if !seen_names.insert(name) {
-            bail!("Duplicate file name detected in selection: {:?}", name);
+/// Where one item goes when put into a directory, see [`place_into_dir`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Placement {
+    /// to this path, which is free unless it is a conflict
+    New(PathBuf),
+    /// to this existing path, which is trashed once its replacement is written
+    Replace(PathBuf),
+    /// into this existing directory, see [`merge_pairs`]
+    Merge(PathBuf),
+    /// not touched
+    Skip,
+}
+
+/// Decide where every item of `items` goes in `dest_dir` under `policy`.
+/// Items without a file name are left out, an item already in `dest_dir` is skipped.
+/// Items sharing a name, e.g. from a recursive view, are numbered whatever the policy.
+pub fn place_into_dir<P: AsRef<Path>>(
+    items: &[P],
+    dest_dir: &Path,
+    policy: ConflictPolicy,
+) -> Vec<(PathBuf, Placement)> {
+    // 前面的项目占用的目标
+    let mut taken = HashSet::new();
+    items
+        .iter()
+        .filter_map(|item| {
+            let src = item.as_ref();
+            let name = src.file_name()?;
+            let target = dest_dir.join(name);
+            let is_free = |path: &Path| !taken.contains(path) && path.symlink_metadata().is_err();
+
+            let placement = if target == src {
+                Placement::Skip
+            } else if taken.contains(&target) {
+                Placement::New(numbered_target(dest_dir, name, src.is_dir(), is_free))
+            } else if is_free(&target) {
+                Placement::New(target)
+            } else {
+                match policy {
+                    ConflictPolicy::Skip => Placement::Skip,
+                    ConflictPolicy::Overwrite => Placement::Replace(target),
+                    ConflictPolicy::Merge if src.is_dir() && target.is_dir() => {
+                        Placement::Merge(target)
+                    }
+                    ConflictPolicy::Rename => {
+                        Placement::New(numbered_target(dest_dir, name, src.is_dir(), is_free))
+                    }
+                    // 留给 check_into_dir 报告冲突
+                    ConflictPolicy::Ask | ConflictPolicy::Merge => Placement::New(target),
+                }
+            };
+            if let Placement::New(path) | Placement::Replace(path) | Placement::Merge(path) =
+                &placement
+            {
+                taken.insert(path.clone());
+            }
+            Some((src.to_path_buf(), placement))
+        })
+        .collect()
+}
+
+/// `name (n).ext` in `dir` for the first free `n` from 1.
+/// Directories and dot files keep their whole name before the suffix.
+fn numbered_target(
+    dir: &Path,
+    name: &OsStr,
+    is_dir: bool,
+    is_free: impl Fn(&Path) -> bool,
+) -> PathBuf {
+    let name = name.to_string_lossy();
+    // 开头的点不算扩展名
+    let split = name
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| *c == '.')
+        .map(|(i, _)| i)
+        .filter(|_| !is_dir)
+        .unwrap_or(name.len());
+    let (stem, ext) = name.split_at(split);
+    (1..)
+        .map(|n| dir.join(format!("{} ({}){}", stem, n, ext)))
+        .find(|path| is_free(path))
+        .expect("there is always a free name")
+}
+
+/// The `(from, to)` pairs that merge the directory `src` into the existing directory `dst`,
+/// sorted. Sub directories on both sides are merged too, anything else goes over as a whole.
+/// Fails if a directory of `src` cannot be listed, nothing of it may be left behind.
+pub fn merge_pairs(src: &Path, dst: &Path) -> Res<Vec<(PathBuf, PathBuf)>> {
+    let mut children = fs::read_dir(src)
+        .and_then(|entries| {
+            entries
+                .map(|e| e.map(|e| e.path()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .with_context(|| format!("Failed to read directory '{}'", src.display()))?;
+    children.sort();
+
+    let mut pairs = Vec::new();
+    for child in children {
+        let Some(name) = child.file_name() else {
+            continue;
+        };
+        let target = dst.join(name);
+        // 不跟随符号链接
+        let child_is_dir = child.symlink_metadata().is_ok_and(|m| m.is_dir());
+        if child_is_dir && target.symlink_metadata().is_ok_and(|m| m.is_dir()) {
+            pairs.extend(merge_pairs(&child, &target)?);
+        } else {
+            pairs.push((child, target));
+        }
+    }
+    Ok(pairs)
+}
+
+/// Remove the directory `src` emptied by a merge, bottom-up.
+/// Anything still in it is an error instead of being deleted.
+fn remove_merged(src: &Path) -> Res<()> {
+    for entry in fs::read_dir(src)? {
+        let path = entry?.path();
+        if path.symlink_metadata().is_ok_and(|m| m.is_dir()) {
+            remove_merged(&path)?;
+        }
+    }
+    fs::remove_dir(src).with_context(|| format!("Failed to remove merged '{}'", src.display()))
+}
+
+/// Move or copy `src` as `placement` says, `transfer` handles one `(from, to)` pair.
+/// A replaced target is kept until its replacement is written next to it under a temporary
+/// name, then it goes to the trash. If that fails, `revert` takes the replacement back.
+fn place<T, R>(src: &Path, placement: &Placement, mut transfer: T, revert: R) -> Res<()>
+where
+    T: FnMut(&Path, &Path) -> Res<()>,
+    R: FnOnce(&Path, &Path) -> Res<()>,
+{
+    match placement {
+        Placement::Skip => Ok(()),
+        Placement::New(dst) => transfer(src, dst),
+        Placement::Merge(dst) => merge_pairs(src, dst)?
+            .iter()
+            .try_for_each(|(from, to)| transfer(from, to)),
+        Placement::Replace(dst) => {
+            let tmp = replacement_path(dst);
+            let res = transfer(src, &tmp).and_then(|_| {
+                trash(&[dst]).with_context(|| format!("Failed to overwrite '{}'", dst.display()))
+            });
+            if let Err(e) = res {
+                if tmp.symlink_metadata().is_ok()
+                    && let Err(cleanup) = revert(&tmp, src)
+                {
+                    tracing::error!("Failed to clean up '{}': {:?}", tmp.display(), cleanup);
+                }
+                return Err(e);
+            }
+            fs::rename(&tmp, dst).with_context(|| {
+                format!(
+                    "Failed to rename '{}' to '{}'",
+                    tmp.display(),
+                    dst.display()
+                )
+            })
+        }
+    }
+}
+
+/// A free hidden name next to `dst` to write its replacement to
+fn replacement_path(dst: &Path) -> PathBuf {
+    let dir = dst.parent().unwrap_or(Path::new(""));
+    let name = format!(
+        ".{}.ef-tmp",
+        dst.file_name().unwrap_or_default().to_string_lossy()
+    );
+    let is_free = |path: &Path| path.symlink_metadata().is_err();
+    let tmp = dir.join(&name);
+    if is_free(&tmp) {
+        tmp
+    } else {
+        numbered_target(dir, OsStr::new(&name), true, is_free)
+    }
+}
+
+/// Fail with the first conflict, if any
+fn bail_on(conflicts: Vec<Conflict>) -> Res<()> {
+    match conflicts.into_iter().next() {
+        Some(conflict) => bail!("{}", conflict),
+        None => Ok(()),
+    }
+}
+
+/// Every source must exist
+pub fn check_sources<P: AsRef<Path>>(items: &[P]) -> Vec<Conflict> {
+    items
+        .iter()
+        .map(AsRef::as_ref)
+        .filter(|path| path.symlink_metadata().is_err())
+        .map(|path| Conflict::SourceMissing(path.to_path_buf()))
+        .collect()
+}
+
+/// What [`organize`] and [`copy`] check before putting `items` into `dest_dir`,
+/// which may already exist as a directory. Which existing names conflict depends on `policy`.
+pub fn check_into_dir<P: AsRef<Path>>(
+    items: &[P],
+    dest_dir: &Path,
+    policy: ConflictPolicy,
+) -> Vec<Conflict> {
+    let mut conflicts = Vec::new();
+    if dest_dir.exists() && !dest_dir.is_dir() {
+        conflicts.push(Conflict::DestinationExists(dest_dir.to_path_buf()));
+    }
+    conflicts.extend(check_sources(items));
+
+    // 重名的项目由 place_into_dir 编号
+    conflicts.extend(
+        items
+            .iter()
+            .map(AsRef::as_ref)
+            .filter(|path| path.file_name().is_none())
+            .map(|path| Conflict::InvalidName(path.to_path_buf())),
+    );
+
+    // 询问时所有已存在的名字，合并时两边都有的文件
+    if matches!(policy, ConflictPolicy::Ask | ConflictPolicy::Merge) && dest_dir.is_dir() {
+        let exists = |path: &PathBuf| path.symlink_metadata().is_ok();
+        let mut existing = Vec::new();
+        for (src, placement) in place_into_dir(items, dest_dir, policy) {
+            match placement {
+                Placement::New(target) if exists(&target) => existing.push(target),
+                // 读不了的目录在执行时报错
+                Placement::Merge(target) => existing.extend(
+                    merge_pairs(&src, &target)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|(_, to)| to)
+                        .filter(exists),
+                ),
+                _ => {}
+            }
+        }
+        conflicts.extend(existing.into_iter().map(Conflict::DestinationExists));
+    }
+    conflicts
+}
+
+/// What [`organize`] checks: `dest_dir` has to be new, unless merging into it
+pub fn check_organize<P: AsRef<Path>>(
+    items: &[P],
+    dest_dir: &Path,
+    policy: ConflictPolicy,
+) -> Vec<Conflict> {
+    let mut conflicts = Vec::new();
+    // 不是目录的情况由 check_into_dir 报告
+    if policy != ConflictPolicy::Merge && dest_dir.is_dir() {
+        conflicts.push(Conflict::DestinationExists(dest_dir.to_path_buf()));
+    }
+    conflicts.extend(check_into_dir(items, dest_dir, policy));
+    conflicts
+}
+
+/// What [`rename`] checks, pairs whose target equals the source are ignored
+pub fn check_rename<P: AsRef<Path>, Q: AsRef<Path>>(pairs: &[(P, Q)]) -> Vec<Conflict> {
+    let mut conflicts = Vec::new();
+    let mut seen_targets = HashSet::new();
+    for (from, to) in pairs.iter().map(|(from, to)| (from.as_ref(), to.as_ref())) {
+        if from == to {
+            continue;
+        }
+        if !from.exists() {
+            conflicts.push(Conflict::SourceMissing(from.to_path_buf()));
+        }
+        if to.file_name().is_none_or(|name| name.is_empty()) {
+            conflicts.push(Conflict::InvalidName(to.to_path_buf()));
+        }
+        if !seen_targets.insert(to) {
+            conflicts.push(Conflict::DuplicateTarget(to.to_path_buf()));
+        } else if to.exists() {
+            conflicts.push(Conflict::DestinationExists(to.to_path_buf()));
         }
     }
+    conflicts
+}
+
+/// Fail if `cancel` was triggered
+fn check_cancel(cancel: Option<&CancelToken>) -> Res<()> {
+    if cancel.is_some_and(CancelToken::is_cancelled) {
+        bail!(CANCELLED);
+    }
+    Ok(())
+}
+
+/// Gather `items` into the new directory `target_dir_path`.
+/// It may only exist already with [`ConflictPolicy::Merge`], see [`move_into`] for the rest.
+pub fn organize<P: AsRef<Path>>(
+    items: &[P],
+    target_dir_path: &Path,
+    policy: ConflictPolicy,
+    progress_cb: Option<ProgressFn>,
+    cancel: Option<&CancelToken>,
+) -> Res<()> {
+    bail_on(check_organize(items, target_dir_path, policy))?;
+    move_into(items, target_dir_path, policy, progress_cb, cancel)
+}
+
+/// Move `items` into the directory `target_dir_path`, which is created if needed.
+/// Existing names are handled by `policy`, across devices items are copied then removed.
+/// Checks `cancel` between items, the ones already moved stay moved.
+/// Progress is in bytes, a rename on the same device counts the whole item at once.
+pub fn move_into<P: AsRef<Path>>(
+    items: &[P],
+    target_dir_path: &Path,
+    policy: ConflictPolicy,
+    progress_cb: Option<ProgressFn>,
+    cancel: Option<&CancelToken>,
+) -> Res<()> {
+    let dest_dir = target_dir_path;
+    bail_on(check_into_dir(items, dest_dir, policy))?;
+    check_cancel(cancel)?;
 
     fs::create_dir_all(dest_dir)
         .with_context(|| format!("Failed to create directory '{}'", dest_dir.display()))?;
 
-    for item_path in items {
-        let path = item_path.as_ref();
-        let file_name = path.file_name().unwrap();
-        let dst = dest_dir.join(file_name);
+    let placements = place_into_dir(items, dest_dir, policy);
+    // 只有需要汇报进度时才统计大小
+    let sizes: Vec<u64> = placements
+        .iter()
+        .map(|(src, placement)| match (&progress_cb, placement) {
+            (None, _) | (_, Placement::Skip) => 0,
+            _ => total_size(src),
+        })
+        .collect();
+    let total: u64 = sizes.iter().sum();
 
-        move_item(path, &dst).with_context(|| {
-            format!("Failed to move '{}' to '{}'", path.display(), dst.display())
-        })?;
+    let mut done = 0;
+    for ((path, placement), size) in placements.iter().zip(sizes) {
+        check_cancel(cancel)?;
+        let item_start = done;
+        let mut on_bytes = |n: u64| {
+            done += n;
+            if let Some(cb) = &progress_cb {
+                cb(done, total);
+            }
+        };
+        place(
+            path,
+            placement,
+            |from, to| {
+                move_item(from, to, &mut on_bytes, cancel).with_context(|| {
+                    format!("Failed to move '{}' to '{}'", from.display(), to.display())
+                })
+            },
+            |tmp, src| move_item(tmp, src, &mut |_| {}, None),
+        )?;
+        // 合并后只剩下空目录
+        if let Placement::Merge(_) = placement {
+            remove_merged(path)?;
+        }
+
+        done = item_start + size;
+        if let Some(cb) = &progress_cb {
+            cb(done, total);
+        }
     }
     Ok(())
 }
 
+/// Copy `items` into the directory `target_dir_path`, reporting progress in bytes.
+/// Existing names are handled by `policy`.
+/// Checks `cancel` between chunks, and removes what was copied if cancelled.
 pub fn copy<P: AsRef<Path>>(
     items: &[P],
     target_dir_path: P,
-    progress_cb: Option<Arc<dyn Fn(f32) + Send + Sync>>,
+    policy: ConflictPolicy,
+    progress_cb: Option<ProgressFn>,
+    cancel: Option<&CancelToken>,
 ) -> Res<()> {
     let target_dir_path = target_dir_path.as_ref();
-    if target_dir_path.exists() {
-        bail!(
-            "Destination directory '{}' already exists.",
-            target_dir_path.display()
-        );
-    }
-
-    for item in items {
-        if !item.as_ref().exists() {
-            bail!("Source item '{}' does not exist.", item.as_ref().display());
-        }
-    }
+    bail_on(check_into_dir(items, target_dir_path, policy))?;
 
+    let created = !target_dir_path.exists();
     fs::create_dir_all(target_dir_path)
         .with_context(|| format!("Failed to create directory '{}'", target_dir_path.display()))?;
 
-    // Calculate total files for progress reporting
-    let mut total_files = 0;
-    if progress_cb.is_some() {
-        for item in items {
-            let path = item.as_ref();
-            if path.is_dir() {
-                total_files += count_files(path);
-            } else {
-                total_files += 1;
-            }
-        }
-    }
+    let placements = place_into_dir(items, target_dir_path, policy);
+    // Calculate total bytes for progress reporting
+    let total: u64 = match &progress_cb {
+        Some(_) => placements
+            .iter()
+            .filter(|(_, placement)| *placement != Placement::Skip)
+            .map(|(src, _)| total_size(src))
+            .sum(),
+        None => 0,
+    };
 
-    let mut current_files = 0;
-    let mut on_progress = || {
-        current_files += 1;
-        if let Some(cb) = &progress_cb
-            && total_files > 0 {
-                cb(current_files as f32 / total_files as f32);
-            }
+    let mut done = 0;
+    let mut on_bytes = |n: u64| {
+        done += n;
+        if let Some(cb) = &progress_cb {
+            cb(done, total);
+        }
     };
 
-    for item in items {
-        let src = item.as_ref();
-        let file_name = src.file_name().ok_or_else(|| {
-            color_eyre::eyre::eyre!("Could not get file name for '{}'", src.display())
-        })?;
-        let dst = target_dir_path.join(file_name);
+    // 本次复制产生的路径，取消时删掉
+    let mut copied = Vec::new();
+    let res = placements.iter().try_for_each(|(src, placement)| {
+        place(
+            src,
+            placement,
+            |from, to| {
+                copied.push(to.to_path_buf());
+                if from.is_dir() {
+                    copy_dir_all(from, to, &mut on_bytes, cancel)
+                } else {
+                    copy_file(from, to, &mut on_bytes, cancel).with_context(|| {
+                        format!(
+                            "Failed to copy file '{}' to '{}'",
+                            from.display(),
+                            to.display()
+                        )
+                    })
+                }
+            },
+            |tmp, _| delete(&[tmp]),
+        )
+    });
 
-        if src.is_dir() {
-            copy_dir_all(src, &dst, &mut on_progress)?;
+    if res.is_err() && cancel.is_some_and(CancelToken::is_cancelled) {
+        let cleanup = if created {
+            vec![target_dir_path.to_path_buf()]
         } else {
-            fs::copy(src, &dst).with_context(|| {
-                format!(
-                    "Failed to copy file '{}' to '{}'",
-                    src.display(),
-                    dst.display()
-                )
-            })?;
-            on_progress();
+            copied
+        };
+        for path in cleanup.iter().filter(|p| p.symlink_metadata().is_ok()) {
+            if let Err(e) = delete(&[path]) {
+                tracing::error!("Failed to clean up '{}': {:?}", path.display(), e);
+            }
         }
     }
+    res
+}
+
+/// Rename every `(from, to)` pair in place.
+/// Nothing is touched if two targets collide, a target already exists or a source is missing.
+/// Pairs whose target equals the source are skipped.
+pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(pairs: &[(P, Q)]) -> Res<()> {
+    let pairs: Vec<(&Path, &Path)> = pairs
+        .iter()
+        .map(|(from, to)| (from.as_ref(), to.as_ref()))
+        .filter(|(from, to)| from != to)
+        .collect();
+
+    bail_on(check_rename(&pairs))?;
+
+    for (from, to) in pairs {
+        fs::rename(from, to).with_context(|| {
+            format!(
+                "Failed to rename '{}' to '{}'",
+                from.display(),
+                to.display()
+            )
+        })?;
+    }
     Ok(())
 }
 
-/// 增强版的移动函数，支持跨分区移动
-fn move_item(src: &Path, dst: &Path) -> Res<()> {
+/// 增强版的移动函数，支持跨分区移动。
+/// 跨分区时退化为复制加删除，`on_bytes` 收到每次复制的字节数
+pub(crate) fn move_item<F>(
+    src: &Path,
+    dst: &Path,
+    on_bytes: &mut F,
+    cancel: Option<&CancelToken>,
+) -> Res<()>
+where
+    F: FnMut(u64),
+{
     if let Err(e) = fs::rename(src, dst) {
         if e.raw_os_error() == Some(18) || e.kind() == io::ErrorKind::CrossesDevices {
-            if src.is_dir() {
-                copy_dir_all(src, dst, &mut || {})?;
-                fs::remove_dir_all(src)?;
-            } else {
-                fs::copy(src, dst)?;
-                fs::remove_file(src)?;
-            }
+            copy_then_remove(src, dst, on_bytes, cancel)?;
         } else {
             return Err(e).map_err(|err| err.into());
         }
@@ -200,9 +660,46 @@ fn move_item(src: &Path, dst: &Path) -> Res<()> {
     Ok(())
 }
 
-fn copy_dir_all<F>(src: &Path, dst: &Path, on_copy: &mut F) -> Res<()>
+/// The cross-device move: copy `src` to `dst`, then remove `src`.
+/// A cancelled or failed copy removes what it wrote to `dst`, leaving `src` untouched
+fn copy_then_remove<F>(
+    src: &Path,
+    dst: &Path,
+    on_bytes: &mut F,
+    cancel: Option<&CancelToken>,
+) -> Res<()>
 where
-    F: FnMut(),
+    F: FnMut(u64),
+{
+    let copied = if src.is_dir() {
+        copy_dir_all(src, dst, on_bytes, cancel)
+    } else {
+        copy_file(src, dst, on_bytes, cancel)
+    };
+    if let Err(e) = copied {
+        if dst.symlink_metadata().is_ok()
+            && let Err(cleanup) = delete(&[dst])
+        {
+            tracing::error!("Failed to clean up '{}': {:?}", dst.display(), cleanup);
+        }
+        return Err(e);
+    }
+    if src.is_dir() {
+        fs::remove_dir_all(src)?;
+    } else {
+        fs::remove_file(src)?;
+    }
+    Ok(())
+}
+
+fn copy_dir_all<F>(
+    src: &Path,
+    dst: &Path,
+    on_bytes: &mut F,
+    cancel: Option<&CancelToken>,
+) -> Res<()>
+where
+    F: FnMut(u64),
 {
     let walker = WalkBuilder::new(src)
         .hidden(false)
@@ -210,6 +707,7 @@ where
         .build();
 
     for result in walker {
+        check_cancel(cancel)?;
         let entry = result?;
         let path = entry.path();
         let rel_path = path.strip_prefix(src)?;
@@ -222,21 +720,50 @@ where
         if file_type.is_dir() {
             fs::create_dir_all(&target_path)?;
         } else if file_type.is_file() {
-            fs::copy(path, &target_path)?;
-            on_copy();
+            copy_file(path, &target_path, on_bytes, cancel)?;
         }
     }
     Ok(())
 }
 
-fn count_files(path: &Path) -> usize {
+/// 大文件分块复制的块大小
+const COPY_CHUNK: usize = 1024 * 1024;
+
+/// Copy one file in chunks, calling `on_bytes` after each chunk and checking `cancel` between them.
+/// Permissions are copied like `fs::copy` does.
+fn copy_file<F>(src: &Path, dst: &Path, on_bytes: &mut F, cancel: Option<&CancelToken>) -> Res<()>
+where
+    F: FnMut(u64),
+{
+    let mut reader = fs::File::open(src)?;
+    let mut writer = fs::File::create(dst)?;
+    let mut buf = vec![0; COPY_CHUNK];
+    loop {
+        check_cancel(cancel)?;
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        writer.write_all(&buf[..n])?;
+        on_bytes(n as u64);
+    }
+    writer.set_permissions(reader.metadata()?.permissions())?;
+    Ok(())
+}
+
+/// Size in bytes of a file, or of every file under a directory
+pub(crate) fn total_size(path: &Path) -> u64 {
     WalkBuilder::new(path)
         .hidden(false)
         .git_ignore(false)
         .build()
         .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().map(|t| t.is_file()).unwrap_or(false))
-        .count()
+        .filter(|e| e.file_type().is_some_and(|t| t.is_file()))
+        .filter_map(|e| e.metadata().ok())
+        .map(|m| m.len())
+        .sum()
 }
 
 /// 静态工具函数（不依赖过滤规则）
@@ -256,3 +783,434 @@ pub fn trash<P: AsRef<Path>>(items: &[P]) -> Res<()> {
     trash::delete_all(items).with_context(|| "Failed to move items to trash")?;
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_rename_items() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let a = dir.path().join("a.txt");
+        let b = dir.path().join("b.txt");
+        fs::write(&a, "a")?;
+        fs::write(&b, "b")?;
+
+        rename(&[
+            (a.clone(), dir.path().join("pre-a.txt")),
+            (b.clone(), b.clone()),
+        ])?;
+
+        assert!(!a.exists());
+        assert_eq!(fs::read_to_string(dir.path().join("pre-a.txt"))?, "a");
+        assert!(b.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_rename_refuses_collisions() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let a = dir.path().join("a.txt");
+        let b = dir.path().join("b.txt");
+        let c = dir.path().join("c.txt");
+        fs::write(&a, "a")?;
+        fs::write(&b, "b")?;
+        fs::write(&c, "c")?;
+
+        // two targets collide
+        assert!(
+            rename(&[
+                (a.clone(), dir.path().join("x.txt")),
+                (b.clone(), dir.path().join("x.txt"))
+            ])
+            .is_err()
+        );
+        // target exists
+        assert!(rename(&[(a.clone(), c.clone())]).is_err());
+        // nothing was renamed
+        assert!(a.exists() && b.exists() && !dir.path().join("x.txt").exists());
+        assert_eq!(fs::read_to_string(&c)?, "c");
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_cancelled_cleans_up() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let src = dir.path().join("src");
+        fs::create_dir_all(src.join("nested"))?;
+        fs::write(src.join("a.txt"), "a")?;
+        fs::write(src.join("nested").join("b.txt"), "b")?;
+        let dst = dir.path().join("dst");
+
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let err = copy(
+            std::slice::from_ref(&src),
+            dst.clone(),
+            ConflictPolicy::Ask,
+            None,
+            Some(&cancel),
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "Cancelled");
+        assert!(!dst.exists());
+        assert!(src.join("nested").join("b.txt").exists());
+
+        copy(
+            std::slice::from_ref(&src),
+            dst.clone(),
+            ConflictPolicy::Ask,
+            None,
+            Some(&CancelToken::new()),
+        )?;
+        assert!(dst.join("src").join("nested").join("b.txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cross_device_move_cancelled_cleans_up() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let src = dir.path().join("src");
+        fs::create_dir_all(src.join("nested"))?;
+        fs::write(src.join("a.txt"), "a")?;
+        fs::write(src.join("nested").join("b.txt"), "b")?;
+        let file = dir.path().join("c.txt");
+        fs::write(&file, "c".repeat(COPY_CHUNK + 1))?;
+
+        for (src, dst) in [
+            (&src, dir.path().join("dst")),
+            (&file, dir.path().join("d.txt")),
+        ] {
+            // 复制了一部分之后取消
+            let cancel = CancelToken::new();
+            let err =
+                copy_then_remove(src, &dst, &mut |_| cancel.cancel(), Some(&cancel)).unwrap_err();
+            assert_eq!(err.to_string(), "Cancelled");
+            assert!(!dst.exists());
+            assert!(src.exists());
+        }
+        assert!(src.join("nested").join("b.txt").exists());
+
+        let dst = dir.path().join("dst");
+        copy_then_remove(&src, &dst, &mut |_| {}, None)?;
+        assert!(!src.exists());
+        assert_eq!(fs::read_to_string(dst.join("nested").join("b.txt"))?, "b");
+        Ok(())
+    }
+
+    #[test]
+    fn test_overwrite_keeps_old_until_done() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let a = dir.path().join("a.txt");
+        let dst = dir.path().join("dst");
+        fs::create_dir_all(&dst)?;
+        fs::write(&a, "a".repeat(COPY_CHUNK + 1))?;
+        fs::write(dst.join("a.txt"), "old")?;
+
+        // 写了一块之后取消
+        let cancel = CancelToken::new();
+        let token = cancel.clone();
+        let progress: ProgressFn = std::sync::Arc::new(move |_, _| token.cancel());
+        let res = copy(
+            &[&a],
+            &dst,
+            ConflictPolicy::Overwrite,
+            Some(progress),
+            Some(&cancel),
+        );
+        assert_eq!(res.unwrap_err().root_cause().to_string(), "Cancelled");
+        assert_eq!(fs::read_to_string(dst.join("a.txt"))?, "old");
+        assert_eq!(fs::read_dir(&dst)?.count(), 1);
+
+        move_into(&[&a], &dst, ConflictPolicy::Overwrite, None, None)?;
+        assert!(!a.exists());
+        assert_eq!(fs::read(dst.join("a.txt"))?.len(), COPY_CHUNK + 1);
+        assert_eq!(fs::read_dir(&dst)?.count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_organize_cancelled() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let a = dir.path().join("a.txt");
+        fs::write(&a, "a")?;
+        let dst = dir.path().join("dst");
+
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        assert!(
+            organize(
+                std::slice::from_ref(&a),
+                &dst,
+                ConflictPolicy::Ask,
+                None,
+                Some(&cancel)
+            )
+            .is_err()
+        );
+        assert!(a.exists());
+        assert!(!dst.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_reports_bytes() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let big = dir.path().join("big.bin");
+        let src = dir.path().join("src");
+        fs::write(&big, vec![7u8; 2 * COPY_CHUNK + 5])?;
+        fs::create_dir_all(&src)?;
+        fs::write(src.join("a.txt"), "abc")?;
+        let total = (2 * COPY_CHUNK + 5 + 3) as u64;
+
+        let calls = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorder = Arc::clone(&calls);
+        let progress: ProgressFn = Arc::new(move |done, total| {
+            recorder.lock().unwrap().push((done, total));
+        });
+        copy(
+            &[big, src],
+            dir.path().join("dst"),
+            ConflictPolicy::Ask,
+            Some(progress),
+            None,
+        )?;
+
+        let calls = calls.lock().unwrap();
+        // 大文件分三块，小文件一块
+        assert_eq!(calls.len(), 4);
+        assert!(calls.windows(2).all(|w| w[0].0 < w[1].0));
+        assert!(calls.iter().all(|&(_, t)| t == total));
+        assert_eq!(calls.last(), Some(&(total, total)));
+        assert_eq!(
+            fs::read(dir.path().join("dst").join("big.bin"))?.len(),
+            2 * COPY_CHUNK + 5
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_numbered_target() {
+        let dir = Path::new("/d");
+        let free = |p: &Path| p != Path::new("/d/a (1).txt");
+        assert_eq!(
+            numbered_target(dir, "a.txt".as_ref(), false, free),
+            Path::new("/d/a (2).txt")
+        );
+        let free = |_: &Path| true;
+        assert_eq!(
+            numbered_target(dir, "x.tar.gz".as_ref(), false, free),
+            Path::new("/d/x (1).tar.gz")
+        );
+        assert_eq!(
+            numbered_target(dir, ".bashrc".as_ref(), false, free),
+            Path::new("/d/.bashrc (1)")
+        );
+        assert_eq!(
+            numbered_target(dir, "v1.2".as_ref(), true, free),
+            Path::new("/d/v1.2 (1)")
+        );
+    }
+
+    #[test]
+    fn test_conflict_policies() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let setup = |name: &str| -> Res<(PathBuf, PathBuf, PathBuf)> {
+            let root = dir.path().join(name);
+            let src = root.join("src");
+            let dst = root.join("dst");
+            fs::create_dir_all(src.join("sub"))?;
+            fs::create_dir_all(dst.join("sub"))?;
+            fs::write(src.join("a.txt"), "new")?;
+            fs::write(src.join("sub").join("b.txt"), "b")?;
+            fs::write(dst.join("a.txt"), "old")?;
+            fs::write(dst.join("sub").join("c.txt"), "c")?;
+            Ok((src.join("a.txt"), src.join("sub"), dst))
+        };
+
+        // ask refuses an existing destination, as before
+        let (a, sub, dst) = setup("ask")?;
+        assert!(organize(&[&a, &sub], &dst, ConflictPolicy::Ask, None, None).is_err());
+        assert!(a.exists());
+
+        let (a, sub, dst) = setup("skip")?;
+        move_into(&[&a, &sub], &dst, ConflictPolicy::Skip, None, None)?;
+        assert!(a.exists() && sub.exists());
+        assert_eq!(fs::read_to_string(dst.join("a.txt"))?, "old");
+
+        let (a, sub, dst) = setup("overwrite")?;
+        copy(&[&a, &sub], &dst, ConflictPolicy::Overwrite, None, None)?;
+        assert_eq!(fs::read_to_string(dst.join("a.txt"))?, "new");
+        assert!(dst.join("sub").join("b.txt").exists());
+        assert!(!dst.join("sub").join("c.txt").exists());
+
+        let (a, sub, dst) = setup("rename")?;
+        move_into(&[&a, &sub], &dst, ConflictPolicy::Rename, None, None)?;
+        assert_eq!(fs::read_to_string(dst.join("a (1).txt"))?, "new");
+        assert_eq!(fs::read_to_string(dst.join("a.txt"))?, "old");
+        assert!(dst.join("sub (1)").join("b.txt").exists());
+
+        // merge fails on the file existing on both sides, and touches nothing
+        let (a, sub, dst) = setup("merge")?;
+        assert_eq!(
+            check_into_dir(&[&a, &sub], &dst, ConflictPolicy::Merge),
+            [Conflict::DestinationExists(dst.join("a.txt"))]
+        );
+        assert!(move_into(&[&a, &sub], &dst, ConflictPolicy::Merge, None, None).is_err());
+        assert!(a.exists() && sub.exists());
+        move_into(&[&sub], &dst, ConflictPolicy::Merge, None, None)?;
+        assert!(!sub.exists());
+        assert!(dst.join("sub").join("b.txt").exists());
+        assert!(dst.join("sub").join("c.txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_keeps_what_was_not_moved() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let src = dir.path().join("src");
+        fs::create_dir_all(src.join("a").join("b"))?;
+        remove_merged(&src)?;
+        assert!(!src.exists());
+
+        fs::create_dir_all(src.join("a"))?;
+        fs::write(src.join("a").join("left.txt"), "x")?;
+        assert!(remove_merged(&src).is_err());
+        assert!(src.join("a").join("left.txt").exists());
+
+        assert!(merge_pairs(&dir.path().join("missing"), dir.path()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_and_organize_differ() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let a = dir.path().join("a.txt");
+        let b = dir.path().join("b.txt");
+        let archive = dir.path().join("archive");
+        fs::write(&a, "a")?;
+        fs::write(&b, "b")?;
+        fs::create_dir(&archive)?;
+        fs::write(archive.join("old.txt"), "old")?;
+
+        // organize wants a new directory
+        assert_eq!(
+            check_organize(&[&a], &archive, ConflictPolicy::Ask),
+            [Conflict::DestinationExists(archive.clone())]
+        );
+        assert!(organize(&[&a], &archive, ConflictPolicy::Ask, None, None).is_err());
+        assert!(a.exists());
+
+        // move goes into the existing one
+        assert!(check_into_dir(&[&a], &archive, ConflictPolicy::Ask).is_empty());
+        move_into(&[&a], &archive, ConflictPolicy::Ask, None, None)?;
+        assert!(!a.exists());
+        assert_eq!(fs::read_to_string(archive.join("a.txt"))?, "a");
+        assert!(archive.join("old.txt").exists());
+
+        // both create a missing directory
+        move_into(
+            &[&b],
+            &dir.path().join("new"),
+            ConflictPolicy::Ask,
+            None,
+            None,
+        )?;
+        assert!(dir.path().join("new").join("b.txt").exists());
+
+        // a file is not a directory to move into
+        let file = dir.path().join("new").join("b.txt");
+        assert!(move_into(&[&archive], &file, ConflictPolicy::Merge, None, None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_items_recursive() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path();
+        fs::create_dir_all(root.join("cam1").join("day1"))?;
+        fs::create_dir(root.join("cam2"))?;
+        fs::write(root.join("top.jpg"), "")?;
+        fs::write(root.join("cam1").join("a.jpg"), "")?;
+        fs::write(root.join("cam1").join("day1").join("a.jpg"), "")?;
+        fs::write(root.join("cam2").join("a.jpg"), "")?;
+
+        let names = |view| -> Res<Vec<String>> {
+            Ok(list_items(root, false, true, view)?
+                .into_iter()
+                .map(|i| i.display_name)
+                .collect())
+        };
+        assert_eq!(names(ViewMode::Flat)?, ["cam1", "cam2", "top.jpg"]);
+        let sep = std::path::MAIN_SEPARATOR;
+        assert_eq!(
+            names(ViewMode::Recursive { max_depth: None })?,
+            [
+                format!("cam1{sep}a.jpg"),
+                format!("cam1{sep}day1{sep}a.jpg"),
+                format!("cam2{sep}a.jpg"),
+                "top.jpg".to_string(),
+            ]
+        );
+        assert_eq!(names(ViewMode::Recursive { max_depth: Some(2) })?.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_same_names_are_numbered() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let items: Vec<PathBuf> = ["cam1", "cam2", "cam3"]
+            .iter()
+            .map(|cam| {
+                fs::create_dir(dir.path().join(cam))?;
+                let path = dir.path().join(cam).join("a.jpg");
+                fs::write(&path, cam)?;
+                Ok(path)
+            })
+            .collect::<Res<_>>()?;
+        let taken = dir.path().join("taken");
+        fs::create_dir(&taken)?;
+        fs::write(taken.join("a.jpg"), "old")?;
+
+        // 已存在的仍按策略处理，批次内的重名编号
+        assert_eq!(
+            check_into_dir(&items, &taken, ConflictPolicy::Ask),
+            [Conflict::DestinationExists(taken.join("a.jpg"))]
+        );
+        copy(&items, taken.clone(), ConflictPolicy::Skip, None, None)?;
+        assert_eq!(fs::read_dir(&taken)?.count(), 1);
+
+        let dest = dir.path().join("sorted");
+        assert!(check_organize(&items, &dest, ConflictPolicy::Ask).is_empty());
+        organize(&items, &dest, ConflictPolicy::Ask, None, None)?;
+        assert!(!items[0].exists());
+        assert_eq!(fs::read_to_string(dest.join("a (1).jpg"))?, "cam2");
+        assert_eq!(fs::read_to_string(dest.join("a (2).jpg"))?, "cam3");
+        assert_eq!(fs::read_to_string(dest.join("a.jpg"))?, "cam1");
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_reports_progress() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let a = dir.path().join("a.txt");
+        fs::write(&a, "abcd")?;
+
+        let calls = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorder = Arc::clone(&calls);
+        let progress: ProgressFn = Arc::new(move |done, total| {
+            recorder.lock().unwrap().push((done, total));
+        });
+        move_into(
+            &[&a],
+            &dir.path().join("dst"),
+            ConflictPolicy::Ask,
+            Some(progress),
+            None,
+        )?;
+        // 同一设备上直接改名，整项一次算完
+        assert_eq!(calls.lock().unwrap().last(), Some(&(4, 4)));
+        Ok(())
+    }
+}
@@ -1 +1,2 @@
-pub mod rename;
\ No newline at end of file
+pub mod archive;
+pub mod rename;
@@ -0,0 +1,608 @@
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use color_eyre::{
+    Result as Res,
+    eyre::{Context, bail, eyre},
+};
+use flate2::{Compression, write::GzEncoder};
+use ignore::WalkBuilder;
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+use crate::core::service::tasks::ProgressFn;
+
+/// Supported archive formats, chosen by the file extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    /// `.zip`
+    Zip,
+    /// `.tar`
+    Tar,
+    /// `.tar.gz` or `.tgz`
+    TarGz,
+}
+
+impl ArchiveFormat {
+    /// Guess the format from the file name, None if it is not supported
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else if name.ends_with(".tar") {
+            Some(Self::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else {
+            None
+        }
+    }
+
+    /// The file name without the archive extension, e.g. `photos` for `photos.tar.gz`
+    pub fn strip_extension(self, file_name: &str) -> &str {
+        let suffixes: &[&str] = match self {
+            Self::Zip => &[".zip"],
+            Self::Tar => &[".tar"],
+            Self::TarGz => &[".tar.gz", ".tgz"],
+        };
+        suffixes
+            .iter()
+            .find_map(|suffix| {
+                let split = file_name.len().checked_sub(suffix.len())?;
+                file_name
+                    .get(split..)
+                    .filter(|ext| ext.eq_ignore_ascii_case(suffix))
+                    .map(|_| &file_name[..split])
+            })
+            .unwrap_or(file_name)
+    }
+}
+
+/// A file or directory to put into the archive
+struct Entry {
+    path: PathBuf,
+    /// `/` separated path inside the archive
+    name: String,
+    is_dir: bool,
+    /// bytes, 0 for directories
+    size: u64,
+}
+
+/// Pack `items` into a new archive at `archive_path`, recursing into directories.
+/// Every item is stored under its own file name at the archive root.
+/// On failure the partially written archive is removed.
+/// Progress is in bytes of the packed files, reported once per file.
+pub fn archive<P: AsRef<Path>>(
+    items: &[P],
+    archive_path: &Path,
+    progress_cb: Option<ProgressFn>,
+) -> Res<()> {
+    let format = ArchiveFormat::from_path(archive_path).ok_or_else(|| {
+        eyre!(
+            "Unsupported archive format '{}', expected .zip, .tar, .tar.gz or .tgz",
+            archive_path.display()
+        )
+    })?;
+    if archive_path.exists() {
+        bail!("Archive '{}' already exists.", archive_path.display());
+    }
+
+    let entries = collect_entries(items, archive_path)?;
+    let total: u64 = entries.iter().map(|e| e.size).sum();
+    let mut done = 0;
+    let mut on_progress = |n: u64| {
+        done += n;
+        if let Some(cb) = &progress_cb {
+            cb(done, total);
+        }
+    };
+
+    if let Some(parent) = archive_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory '{}'", parent.display()))?;
+    }
+    let file = File::create_new(archive_path)
+        .with_context(|| format!("Failed to create archive '{}'", archive_path.display()))?;
+
+    let result = write_archive(format, file, &entries, &mut on_progress);
+    if result.is_err() {
+        let _ = fs::remove_file(archive_path);
+    }
+    result.with_context(|| format!("Failed to write archive '{}'", archive_path.display()))
+}
+
+fn collect_entries<P: AsRef<Path>>(items: &[P], archive_path: &Path) -> Res<Vec<Entry>> {
+    let mut seen_names = HashSet::new();
+    let mut entries = Vec::new();
+
+    for item in items {
+        let src = item.as_ref();
+        if !src.exists() {
+            bail!("Source item '{}' does not exist.", src.display());
+        }
+        let file_name = src
+            .file_name()
+            .ok_or_else(|| eyre!("Could not get file name for '{}'", src.display()))?;
+        if !seen_names.insert(file_name) {
+            bail!("Duplicate file name detected in selection: {:?}", file_name);
+        }
+
+        let walker = WalkBuilder::new(src)
+            .hidden(false)
+            .git_ignore(false)
+            .build();
+        for result in walker {
+            let entry = result?;
+            let path = entry.path();
+            // 归档文件本身可能就在被打包的目录里
+            if path == archive_path {
+                continue;
+            }
+            let file_type = entry
+                .file_type()
+                .ok_or_else(|| eyre!("Could not get file type for {:?}", path))?;
+            if !file_type.is_dir() && !file_type.is_file() {
+                continue;
+            }
+
+            let rel_path = path.strip_prefix(src)?;
+            let name = Path::new(file_name)
+                .join(rel_path)
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let size = if file_type.is_file() {
+                entry.metadata()?.len()
+            } else {
+                0
+            };
+            entries.push(Entry {
+                path: path.to_path_buf(),
+                name,
+                is_dir: file_type.is_dir(),
+                size,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+fn write_archive<F: FnMut(u64)>(
+    format: ArchiveFormat,
+    file: File,
+    entries: &[Entry],
+    on_progress: &mut F,
+) -> Res<()> {
+    match format {
+        ArchiveFormat::Zip => write_zip(file, entries, on_progress)?,
+        ArchiveFormat::Tar => {
+            write_tar(file, entries, on_progress)?;
+        }
+        ArchiveFormat::TarGz => {
+            let gz = GzEncoder::new(file, Compression::default());
+            write_tar(gz, entries, on_progress)?.finish()?;
+        }
+    }
+    Ok(())
+}
+
+fn write_zip<F: FnMut(u64)>(file: File, entries: &[Entry], on_progress: &mut F) -> Res<()> {
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for entry in entries {
+        if entry.is_dir {
+            zip.add_directory(entry.name.as_str(), options)?;
+        } else {
+            zip.start_file(entry.name.as_str(), options)?;
+            let mut src = File::open(&entry.path)
+                .with_context(|| format!("Failed to open '{}'", entry.path.display()))?;
+            io::copy(&mut src, &mut zip)?;
+            on_progress(entry.size);
+        }
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+/// Returns the inner writer so that compressed streams can be finished by the caller
+fn write_tar<W: Write, F: FnMut(u64)>(writer: W, entries: &[Entry], on_progress: &mut F) -> Res<W> {
+    let mut tar = tar::Builder::new(writer);
+
+    for entry in entries {
+        if entry.is_dir {
+            tar.append_dir(&entry.name, &entry.path)?;
+        } else {
+            tar.append_path_with_name(&entry.path, &entry.name)
+                .with_context(|| format!("Failed to add '{}'", entry.path.display()))?;
+            on_progress(entry.size);
+        }
+    }
+    Ok(tar.into_inner()?)
+}
+
+/// Default destination of [`extract`]: a folder next to the archive, named after it.
+pub fn default_extract_dir(archive_path: &Path) -> Option<PathBuf> {
+    let format = ArchiveFormat::from_path(archive_path)?;
+    let file_name = archive_path.file_name()?.to_string_lossy();
+    let stem = format.strip_extension(&file_name);
+    if stem.is_empty() {
+        return None;
+    }
+    Some(archive_path.with_file_name(stem))
+}
+
+/// Unpack the archive into `dest_dir`, which is created if needed and may already exist.
+///
+/// Entries escaping `dest_dir` (absolute paths, `..`) are rejected, and so is the whole
+/// archive if any of its files already exists, so nothing is written in that case.
+/// Links and other special entries are skipped.
+/// Progress is in uncompressed bytes, reported once per file.
+pub fn extract(archive_path: &Path, dest_dir: &Path, progress_cb: Option<ProgressFn>) -> Res<()> {
+    let format = ArchiveFormat::from_path(archive_path).ok_or_else(|| {
+        eyre!(
+            "Unsupported archive format '{}', expected .zip, .tar, .tar.gz or .tgz",
+            archive_path.display()
+        )
+    })?;
+    if !archive_path.is_file() {
+        bail!("Archive '{}' does not exist.", archive_path.display());
+    }
+
+    // 第一遍：只检查，不写入
+    let mut total = 0;
+    visit_entries(archive_path, format, |rel_path, kind, size, _| {
+        if kind == EntryKind::File {
+            let target = dest_dir.join(rel_path);
+            if target.exists() {
+                bail!("'{}' already exists.", target.display());
+            }
+            total += size;
+        }
+        Ok(())
+    })?;
+
+    // 本次新建的目录和文件，失败时删掉
+    let mut written = Vec::new();
+    create_dirs(dest_dir, &mut written)
+        .with_context(|| format!("Failed to create directory '{}'", dest_dir.display()))?;
+
+    let mut done = 0;
+    let res = visit_entries(archive_path, format, |rel_path, kind, _, reader| {
+        let target = dest_dir.join(rel_path);
+        match kind {
+            EntryKind::Dir => create_dirs(&target, &mut written)?,
+            EntryKind::File => {
+                if let Some(parent) = target.parent() {
+                    create_dirs(parent, &mut written)?;
+                }
+                let mut file = File::create_new(&target)
+                    .with_context(|| format!("Failed to create '{}'", target.display()))?;
+                written.push(target);
+                done += io::copy(reader, &mut file)?;
+                if let Some(cb) = &progress_cb {
+                    cb(done, total.max(done));
+                }
+            }
+            EntryKind::Other => {
+                tracing::warn!("skip special entry {:?} in {:?}", rel_path, archive_path);
+            }
+        }
+        Ok(())
+    })
+    .with_context(|| {
+        format!(
+            "Failed to extract '{}' into '{}'",
+            archive_path.display(),
+            dest_dir.display()
+        )
+    });
+
+    if res.is_err() {
+        // 先删文件再删目录
+        for path in written.iter().rev() {
+            let removed = if path.is_dir() {
+                fs::remove_dir(path)
+            } else {
+                fs::remove_file(path)
+            };
+            if let Err(e) = removed {
+                tracing::error!("Failed to clean up '{}': {:?}", path.display(), e);
+            }
+        }
+    }
+    res
+}
+
+/// What [`extract`] would write into `dest_dir` that does not exist yet, in order,
+/// with true for directories. `dest_dir` and its missing parents come first.
+pub fn extract_targets(archive_path: &Path, dest_dir: &Path) -> Res<Vec<(PathBuf, bool)>> {
+    let format = ArchiveFormat::from_path(archive_path)
+        .ok_or_else(|| eyre!("Unsupported archive format '{}'", archive_path.display()))?;
+
+    let mut targets = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+    let mut add_dirs = |dir: &Path, targets: &mut Vec<(PathBuf, bool)>| {
+        let missing: Vec<&Path> = dir
+            .ancestors()
+            .take_while(|d| !d.as_os_str().is_empty() && !d.exists())
+            .collect();
+        for dir in missing.into_iter().rev() {
+            if seen.insert(dir.to_path_buf()) {
+                targets.push((dir.to_path_buf(), true));
+            }
+        }
+    };
+    add_dirs(dest_dir, &mut targets);
+    visit_entries(archive_path, format, |rel_path, kind, _, _| {
+        let target = dest_dir.join(rel_path);
+        match kind {
+            EntryKind::Dir => add_dirs(&target, &mut targets),
+            EntryKind::File => {
+                if let Some(parent) = target.parent() {
+                    add_dirs(parent, &mut targets);
+                }
+                targets.push((target, false));
+            }
+            EntryKind::Other => {}
+        }
+        Ok(())
+    })?;
+    Ok(targets)
+}
+
+/// Create `dir` and its missing parents, adding the missing ones to `created`, outermost first
+fn create_dirs(dir: &Path, created: &mut Vec<PathBuf>) -> io::Result<()> {
+    let missing: Vec<PathBuf> = dir
+        .ancestors()
+        .take_while(|d| !d.as_os_str().is_empty() && !d.exists())
+        .map(Path::to_path_buf)
+        .collect();
+    fs::create_dir_all(dir)?;
+    created.extend(missing.into_iter().rev());
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EntryKind {
+    Dir,
+    File,
+    /// symlinks, hard links, devices...
+    Other,
+}
+
+/// Call `f` with the sanitized relative path, kind, uncompressed size and content
+/// of every archive entry.
+fn visit_entries<F>(archive_path: &Path, format: ArchiveFormat, mut f: F) -> Res<()>
+where
+    F: FnMut(&Path, EntryKind, u64, &mut dyn Read) -> Res<()>,
+{
+    let file = File::open(archive_path)
+        .with_context(|| format!("Failed to open '{}'", archive_path.display()))?;
+
+    let tar_reader: Box<dyn Read> = match format {
+        ArchiveFormat::Zip => {
+            let mut zip = zip::ZipArchive::new(file)?;
+            for i in 0..zip.len() {
+                let mut entry = zip.by_index(i)?;
+                let rel_path = entry
+                    .enclosed_name()
+                    .ok_or_else(|| eyre!("Unsafe path {:?} in archive", entry.name()))?;
+                let kind = if entry.is_dir() {
+                    EntryKind::Dir
+                } else if entry.is_file() {
+                    EntryKind::File
+                } else {
+                    EntryKind::Other
+                };
+                let size = entry.size();
+                f(&rel_path, kind, size, &mut entry)?;
+            }
+            return Ok(());
+        }
+        ArchiveFormat::Tar => Box::new(file),
+        ArchiveFormat::TarGz => Box::new(flate2::read::GzDecoder::new(file)),
+    };
+
+    let mut tar = tar::Archive::new(tar_reader);
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let rel_path =
+            enclosed_path(&path).ok_or_else(|| eyre!("Unsafe path {:?} in archive", path))?;
+        let entry_type = entry.header().entry_type();
+        let kind = if entry_type.is_dir() {
+            EntryKind::Dir
+        } else if entry_type.is_file() {
+            EntryKind::File
+        } else {
+            EntryKind::Other
+        };
+        let size = entry.size();
+        f(&rel_path, kind, size, &mut entry)?;
+    }
+    Ok(())
+}
+
+/// `path` as a plain relative path, None if it would escape the destination (zip-slip).
+fn enclosed_path(path: &Path) -> Option<PathBuf> {
+    let mut res = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(c) => res.push(c),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !res.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(res)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sample_tree(root: &Path) -> Res<Vec<PathBuf>> {
+        let dir = root.join("photos");
+        fs::create_dir_all(dir.join("nested"))?;
+        fs::write(dir.join("nested/a.jpg"), "a")?;
+        fs::write(root.join("note.txt"), "note")?;
+        Ok(vec![dir, root.join("note.txt")])
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(
+            ArchiveFormat::from_path(Path::new("x/A.ZIP")),
+            Some(ArchiveFormat::Zip)
+        );
+        assert_eq!(
+            ArchiveFormat::from_path(Path::new("a.tar.gz")),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(
+            ArchiveFormat::from_path(Path::new("a.tgz")),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(ArchiveFormat::from_path(Path::new("a.rar")), None);
+    }
+
+    #[test]
+    fn test_archive_zip() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let items = sample_tree(dir.path())?;
+        let out = dir.path().join("out.zip");
+
+        archive(&items, &out, None)?;
+
+        let mut zip = zip::ZipArchive::new(File::open(&out)?)?;
+        let mut content = String::new();
+        zip.by_name("photos/nested/a.jpg")?
+            .read_to_string(&mut content)?;
+        assert_eq!(content, "a");
+        assert!(zip.by_name("note.txt").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_archive_tar_gz() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let items = sample_tree(dir.path())?;
+        let out = dir.path().join("out.tar.gz");
+
+        archive(&items, &out, None)?;
+
+        let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(File::open(&out)?));
+        let names: Vec<String> = tar
+            .entries()?
+            .map(|e| {
+                Ok(e?
+                    .path()?
+                    .to_string_lossy()
+                    .trim_end_matches('/')
+                    .to_string())
+            })
+            .collect::<Res<_>>()?;
+        assert!(names.contains(&"photos/nested/a.jpg".to_string()));
+        assert!(names.contains(&"note.txt".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_extract_dir() {
+        assert_eq!(
+            default_extract_dir(Path::new("/dl/photos.TAR.GZ")),
+            Some(PathBuf::from("/dl/photos"))
+        );
+        assert_eq!(
+            default_extract_dir(Path::new("/dl/a.b.zip")),
+            Some(PathBuf::from("/dl/a.b"))
+        );
+        assert_eq!(default_extract_dir(Path::new("/dl/.zip")), None);
+        assert_eq!(default_extract_dir(Path::new("/dl/a.txt")), None);
+    }
+
+    #[test]
+    fn test_extract_roundtrip() -> Res<()> {
+        for name in ["out.zip", "out.tar", "out.tgz"] {
+            let dir = tempfile::tempdir()?;
+            let items = sample_tree(dir.path())?;
+            let out = dir.path().join(name);
+            archive(&items, &out, None)?;
+
+            let dest = default_extract_dir(&out).unwrap();
+            extract(&out, &dest, None)?;
+            assert_eq!(fs::read_to_string(dest.join("photos/nested/a.jpg"))?, "a");
+            assert_eq!(fs::read_to_string(dest.join("note.txt"))?, "note");
+
+            // extracting again would overwrite files
+            assert!(extract(&out, &dest, None).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_rejects_path_traversal() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let out = dir.path().join("evil.zip");
+        let mut zip = ZipWriter::new(File::create(&out)?);
+        zip.start_file("ok.txt", SimpleFileOptions::default())?;
+        zip.start_file("../evil.txt", SimpleFileOptions::default())?;
+        zip.finish()?;
+
+        let dest = dir.path().join("dest");
+        assert!(extract(&out, &dest, None).is_err());
+        assert!(!dir.path().join("evil.txt").exists());
+        assert!(!dest.join("ok.txt").exists());
+
+        assert_eq!(enclosed_path(Path::new("a/../b")), Some(PathBuf::from("b")));
+        assert_eq!(enclosed_path(Path::new("a/../../b")), None);
+        assert_eq!(enclosed_path(Path::new("/etc/passwd")), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_extract_cleans_up() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let out = dir.path().join("broken.zip");
+        let mut zip = ZipWriter::new(File::create(&out)?);
+        zip.start_file("a/ok.txt", SimpleFileOptions::default())?;
+        zip.write_all(b"ok")?;
+        // 目录条目和已经写出的文件同名，写到这里时失败
+        zip.start_file("a/ok.txt/b.txt", SimpleFileOptions::default())?;
+        zip.finish()?;
+
+        let dest = dir.path().join("dest");
+        assert!(extract(&out, &dest, None).is_err());
+        assert!(!dest.exists());
+
+        // 已有的目录保留，只删掉写进去的
+        fs::create_dir_all(dest.join("a"))?;
+        fs::write(dest.join("keep.txt"), "keep")?;
+        assert!(extract(&out, &dest, None).is_err());
+        assert!(dest.join("a").is_dir());
+        assert!(!dest.join("a/ok.txt").exists());
+        assert_eq!(fs::read_dir(&dest)?.count(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_archive_refuses_existing_or_unknown() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let items = sample_tree(dir.path())?;
+
+        assert!(archive(&items, &dir.path().join("out.rar"), None).is_err());
+        fs::write(dir.path().join("out.zip"), "")?;
+        assert!(archive(&items, &dir.path().join("out.zip"), None).is_err());
+        assert_eq!(fs::read_to_string(dir.path().join("out.zip"))?, "");
+        Ok(())
+    }
+}
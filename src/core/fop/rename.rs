@@ -11,19 +11,31 @@ use serde::{Deserialize, Serialize};
 /// * AddSuffix: Add a suffix.
 /// * RegexReplace: Use regex to replace the original string.
 /// * Pipe: Perform the rules one by one.
-/// # Example
+/// # Syntax
+/// ```text
+/// Replace "old" "new"
+/// SetCase snake            # pascal | camel | snake | kebab | upper | lower
+/// AddPrefix "pre-"
+/// AddSuffix "-suf"
+/// RegexReplace "\d+" "#"
+/// Numbering 1 3            # start, pad
 /// ```
+/// Rule names and case names are case-insensitive. Strings are double-quoted and
+/// support `\"`, `\\`, `\n`, `\t` and `\r`; any other escape is kept as-is, so
+/// regex classes like `"\d+"` need no double escaping.
+/// # Example
+/// ```text
 /// Replace "a" "b" |> AddPrefix "pre-"
 /// ```
-/// will be parsed as 
-/// ``` Rust
+/// will be parsed as
+/// ```text
 /// Pipe(vec![Replace("a", "b"), AddPrefix("pre-")])
 /// ```
 /// and will change
+/// ```text
+/// data.txt, file  ->  pre-dbtb.txt, pre-file
 /// ```
-/// note.txt, file
-/// ```
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub enum RenameRule {
     /// simply replace string `old` to `new`
     Replace {
@@ -48,12 +60,210 @@ pub enum RenameRule {
 
 impl FromStr for RenameRule {
     type Err = String;
-    fn from_str(_s: &str) -> Result<Self, Self::Err> {
-        unimplemented!()
+
+    /// Parse the text syntax described on [`RenameRule`].
+    /// A single rule is returned as-is, two or more chained with `|>` become a [`RenameRule::Pipe`].
+    /// Errors are prefixed with the (1-based, in chars) column that failed.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        let mut parser = RuleParser {
+            tokens: tokens.into_iter().peekable(),
+            end: s.chars().count() + 1,
+        };
+
+        let mut rules = vec![parser.parse_rule()?];
+        while let Some((token, col)) = parser.tokens.next() {
+            match token {
+                Token::Pipe => rules.push(parser.parse_rule()?),
+                other => return Err(format!("column {}: expected `|>`, found {}", col, other)),
+            }
+        }
+
+        if rules.len() == 1 {
+            Ok(rules.remove(0))
+        } else {
+            Ok(RenameRule::Pipe(rules))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// bare word, e.g. a rule name, a case name or a number
+    Word(String),
+    /// double-quoted string, escapes already resolved
+    Str(String),
+    /// `|>`
+    Pipe,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Word(w) => write!(f, "`{}`", w),
+            Token::Str(s) => write!(f, "string {:?}", s),
+            Token::Pipe => write!(f, "`|>`"),
+        }
+    }
+}
+
+/// Split the input into tokens, each paired with its 1-based char column.
+fn tokenize(s: &str) -> Result<Vec<(Token, usize)>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().enumerate().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let col = i + 1;
+        match c {
+            c if c.is_whitespace() => {}
+            '|' => match chars.next() {
+                Some((_, '>')) => tokens.push((Token::Pipe, col)),
+                _ => return Err(format!("column {}: expected `|>`, found a lone `|`", col)),
+            },
+            '"' => {
+                let mut buf = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, '"')) => buf.push('"'),
+                            Some((_, '\\')) => buf.push('\\'),
+                            Some((_, 'n')) => buf.push('\n'),
+                            Some((_, 't')) => buf.push('\t'),
+                            Some((_, 'r')) => buf.push('\r'),
+                            // keep unknown escapes so regexes like "\d+" work unchanged
+                            Some((_, other)) => {
+                                buf.push('\\');
+                                buf.push(other);
+                            }
+                            None => {
+                                return Err(format!("column {}: unterminated string literal", col));
+                            }
+                        },
+                        Some((_, other)) => buf.push(other),
+                        None => {
+                            return Err(format!("column {}: unterminated string literal", col));
+                        }
+                    }
+                }
+                tokens.push((Token::Str(buf), col));
+            }
+            _ => {
+                let mut word = String::from(c);
+                while let Some((_, next)) = chars.peek() {
+                    if next.is_whitespace() || *next == '"' || *next == '|' {
+                        break;
+                    }
+                    word.push(*next);
+                    chars.next();
+                }
+                tokens.push((Token::Word(word), col));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct RuleParser {
+    tokens: std::iter::Peekable<std::vec::IntoIter<(Token, usize)>>,
+    /// column reported when the input ends too early
+    end: usize,
+}
+
+impl RuleParser {
+    fn parse_rule(&mut self) -> Result<RenameRule, String> {
+        let (token, col) = self
+            .tokens
+            .next()
+            .ok_or_else(|| format!("column {}: expected a rule, found end of input", self.end))?;
+        let name = match token {
+            Token::Word(w) => w,
+            other => {
+                return Err(format!(
+                    "column {}: expected a rule name, found {}",
+                    col, other
+                ));
+            }
+        };
+
+        let rule = match name.to_ascii_lowercase().as_str() {
+            "replace" => RenameRule::Replace {
+                old: self.expect_str()?,
+                new: self.expect_str()?,
+            },
+            "setcase" => {
+                let (case, col) = self.expect_word()?;
+                RenameRule::SetCase(case.parse().map_err(|e| format!("column {}: {}", col, e))?)
+            }
+            "addprefix" => RenameRule::AddPrefix(self.expect_str()?),
+            "addsuffix" => RenameRule::AddSuffix(self.expect_str()?),
+            "regexreplace" => {
+                let (pattern, col) = self.expect_str_at()?;
+                regex::Regex::new(&pattern)
+                    .map_err(|e| format!("column {}: invalid regex: {}", col, e))?;
+                RenameRule::RegexReplace {
+                    pattern,
+                    replacement: self.expect_str()?,
+                }
+            }
+            #[allow(deprecated)]
+            "numbering" => RenameRule::Numbering {
+                start: self.expect_number()?,
+                pad: self.expect_number()?,
+            },
+            _ => {
+                return Err(format!(
+                    "column {}: unknown rule `{}`, expected one of Replace, SetCase, AddPrefix, AddSuffix, RegexReplace, Numbering",
+                    col, name
+                ));
+            }
+        };
+        Ok(rule)
+    }
+
+    fn next_arg(&mut self) -> Result<(Token, usize), String> {
+        match self.tokens.next() {
+            Some((Token::Pipe, col)) => {
+                Err(format!("column {}: missing argument before `|>`", col))
+            }
+            Some(x) => Ok(x),
+            None => Err(format!(
+                "column {}: missing argument, found end of input",
+                self.end
+            )),
+        }
+    }
+
+    fn expect_str_at(&mut self) -> Result<(String, usize), String> {
+        match self.next_arg()? {
+            (Token::Str(s), col) => Ok((s, col)),
+            (other, col) => Err(format!(
+                "column {}: expected a quoted string, found {}",
+                col, other
+            )),
+        }
+    }
+
+    fn expect_str(&mut self) -> Result<String, String> {
+        self.expect_str_at().map(|(s, _)| s)
+    }
+
+    fn expect_word(&mut self) -> Result<(String, usize), String> {
+        match self.next_arg()? {
+            (Token::Word(w), col) => Ok((w, col)),
+            (other, col) => Err(format!("column {}: expected a word, found {}", col, other)),
+        }
+    }
+
+    fn expect_number<T: FromStr>(&mut self) -> Result<T, String> {
+        let (word, col) = self.expect_word()?;
+        word.parse()
+            .map_err(|_| format!("column {}: expected a number, found `{}`", col, word))
     }
 }
 
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
 pub enum CaseType {
     /// caseType
     Pascal,
@@ -69,6 +279,24 @@ pub enum CaseType {
     Lower,
 }
 
+impl FromStr for CaseType {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "pascal" => Ok(CaseType::Pascal),
+            "camel" => Ok(CaseType::Camel),
+            "snake" => Ok(CaseType::Snake),
+            "kebab" => Ok(CaseType::Kebab),
+            "upper" => Ok(CaseType::Upper),
+            "lower" => Ok(CaseType::Lower),
+            _ => Err(format!(
+                "unknown case `{}`, expected one of pascal, camel, snake, kebab, upper, lower",
+                s
+            )),
+        }
+    }
+}
+
 pub fn rename(input: Vec<String>, rule: RenameRule) -> Res<Vec<String>> {
     let result: Vec<String> = match rule {
         RenameRule::Replace { old, new } => input
@@ -327,4 +555,95 @@ mod test {
         assert_eq!("CaseType", to_pascal_case("CASE_TYPE"));
         assert_eq!("你好世界", to_pascal_case("你好世界"));
     }
+
+    #[test]
+    fn test_parse_single_rules() {
+        assert_eq!(
+            "Replace \"a\" \"b\"".parse::<RenameRule>(),
+            Ok(RenameRule::Replace {
+                old: "a".to_string(),
+                new: "b".to_string()
+            })
+        );
+        assert_eq!(
+            "SetCase snake".parse::<RenameRule>(),
+            Ok(RenameRule::SetCase(CaseType::Snake))
+        );
+        assert_eq!(
+            "addprefix \"pre-\"".parse::<RenameRule>(),
+            Ok(RenameRule::AddPrefix("pre-".to_string()))
+        );
+        assert_eq!(
+            "AddSuffix \"_bak\"".parse::<RenameRule>(),
+            Ok(RenameRule::AddSuffix("_bak".to_string()))
+        );
+        assert_eq!(
+            r##"RegexReplace "\d+" "#""##.parse::<RenameRule>(),
+            Ok(RenameRule::RegexReplace {
+                pattern: r"\d+".to_string(),
+                replacement: "#".to_string()
+            })
+        );
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_parse_numbering() {
+        assert_eq!(
+            "Numbering 1 3".parse::<RenameRule>(),
+            Ok(RenameRule::Numbering { start: 1, pad: 3 })
+        );
+    }
+
+    #[test]
+    fn test_parse_pipe() {
+        let rule: RenameRule = r#"Replace "a" "b" |> AddPrefix "pre-""#.parse().unwrap();
+        assert_eq!(
+            rule,
+            RenameRule::Pipe(vec![
+                RenameRule::Replace {
+                    old: "a".to_string(),
+                    new: "b".to_string()
+                },
+                RenameRule::AddPrefix("pre-".to_string()),
+            ])
+        );
+        let result = rename(vec!["data.txt".to_string(), "file".to_string()], rule).unwrap();
+        assert_eq!(
+            result,
+            vec!["pre-dbtb.txt".to_string(), "pre-file".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_parse_escapes() {
+        assert_eq!(
+            r#"Replace "say \"hi\"" "a\\b\tc""#.parse::<RenameRule>(),
+            Ok(RenameRule::Replace {
+                old: "say \"hi\"".to_string(),
+                new: "a\\b\tc".to_string()
+            })
+        );
+        assert_eq!(
+            "AddPrefix \"前缀 \"".parse::<RenameRule>(),
+            Ok(RenameRule::AddPrefix("前缀 ".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors_report_column() {
+        let err = |s: &str| s.parse::<RenameRule>().unwrap_err();
+
+        assert!(err("").starts_with("column 1:"));
+        assert!(err("Rename \"a\"").starts_with("column 1: unknown rule `Rename`"));
+        assert!(err("SetCase shouty").starts_with("column 9: unknown case"));
+        assert!(err("Replace \"a\"").starts_with("column 12: missing argument"));
+        assert!(err("Replace \"a\" b").starts_with("column 13: expected a quoted string"));
+        assert!(err("AddPrefix \"a").starts_with("column 11: unterminated string"));
+        assert!(err("AddPrefix \"a\" |> ").starts_with("column 18: expected a rule"));
+        assert!(err("AddPrefix \"a\" | AddSuffix \"b\"").starts_with("column 15: expected `|>`"));
+        assert!(err("AddPrefix \"a\" AddSuffix \"b\"").starts_with("column 15: expected `|>`"));
+        assert!(err(r#"RegexReplace "(" "x""#).starts_with("column 14: invalid regex"));
+        assert!(err("AddPrefix |> AddSuffix \"b\"").starts_with("column 11: missing argument"));
+    }
 }
@@ -0,0 +1,604 @@
+//! On-disk journal of executed file operations, used to undo them later.
+//!
+//! Every line of the journal file is a JSON [`Record`]. Records sharing the same
+//! `batch` were produced by one confirmation in the app and are undone together.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::Write as _,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::{
+    Result as Res,
+    eyre::{Context, bail},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::core::{
+    cmd::Cmd,
+    file_ops::{self, ConflictPolicy, Placement},
+    fop::archive,
+};
+
+/// A single filesystem mutation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FsOp {
+    /// a directory that did not exist before
+    Mkdir {
+        path: PathBuf,
+    },
+    Move {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Copy {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Trash {
+        path: PathBuf,
+    },
+    /// cannot be undone, only recorded
+    Delete {
+        path: PathBuf,
+    },
+    /// the existing `path` goes to the trash for the next operation to take its place,
+    /// `id` tells the old one from its replacement, see [`file_id`]
+    Overwrite {
+        path: PathBuf,
+        id: u64,
+    },
+    /// a new file, e.g. an archive
+    Create {
+        path: PathBuf,
+    },
+}
+
+impl FsOp {
+    /// The operations `cmd` is expected to perform, in order.
+    /// Commands that do not touch the filesystem give an empty list.
+    pub fn plan(cmd: &Cmd) -> Vec<FsOp> {
+        let into_dir = |items: &[PathBuf],
+                        dir: &Path,
+                        policy: ConflictPolicy,
+                        op: fn(PathBuf, PathBuf) -> FsOp| {
+            let mut ops = Vec::new();
+            if !dir.exists() {
+                ops.push(FsOp::Mkdir {
+                    path: dir.to_path_buf(),
+                });
+            }
+            for (src, placement) in file_ops::place_into_dir(items, dir, policy) {
+                match placement {
+                    Placement::Skip => {}
+                    Placement::New(to) => ops.push(op(src, to)),
+                    // 旧目标进回收站，撤销时先拿走新的再还原
+                    Placement::Replace(to) => {
+                        ops.push(FsOp::Overwrite {
+                            id: file_id(&to).unwrap_or_default(),
+                            path: to.clone(),
+                        });
+                        ops.push(op(src, to));
+                    }
+                    // 逐个记录，撤销时不会带走目标目录里原有的东西
+                    // 读不了的目录在执行时报错
+                    Placement::Merge(to) => ops.extend(
+                        file_ops::merge_pairs(&src, &to)
+                            .unwrap_or_default()
+                            .into_iter()
+                            .map(|(from, to)| op(from, to)),
+                    ),
+                }
+            }
+            ops
+        };
+        let moved = |from, to| FsOp::Move { from, to };
+        let copied = |from, to| FsOp::Copy { from, to };
+
+        match cmd {
+            Cmd::Organize(items, dir, policy)
+            | Cmd::Move(items, dir, policy)
+            | Cmd::AsyncOrganize(_, items, dir, policy)
+            | Cmd::AsyncMove(_, items, dir, policy) => into_dir(items, dir, *policy, moved),
+            Cmd::Copy(items, dir, policy) | Cmd::AsyncCopy(_, items, dir, policy) => {
+                into_dir(items, dir, *policy, copied)
+            }
+            Cmd::Rename(pairs) | Cmd::AsyncRename(_, pairs) => pairs
+                .iter()
+                .filter(|(from, to)| from != to)
+                .map(|(from, to)| FsOp::Rename {
+                    from: from.clone(),
+                    to: to.clone(),
+                })
+                .collect(),
+            Cmd::Trash(items) | Cmd::AsyncTrash(_, items) => items
+                .iter()
+                .map(|path| FsOp::Trash { path: path.clone() })
+                .collect(),
+            Cmd::Delete(items) | Cmd::AsyncDelete(_, items) => items
+                .iter()
+                .map(|path| FsOp::Delete { path: path.clone() })
+                .collect(),
+            Cmd::AsyncZip(_, _, archive_path) => vec![FsOp::Create {
+                path: archive_path.clone(),
+            }],
+            // 逐个记录，撤销时不会带走目标目录里原有的东西
+            Cmd::AsyncExtract(_, archive_path, dest) => {
+                archive::extract_targets(archive_path, dest)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(path, is_dir)| {
+                        if is_dir {
+                            FsOp::Mkdir { path }
+                        } else {
+                            FsOp::Create { path }
+                        }
+                    })
+                    .collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Whether the filesystem looks like the operation has been performed.
+    /// Used after execution to only journal what actually happened.
+    pub fn is_applied(&self) -> bool {
+        match self {
+            FsOp::Mkdir { path } | FsOp::Create { path } => path.exists(),
+            FsOp::Move { from, to } | FsOp::Rename { from, to } => !from.exists() && to.exists(),
+            FsOp::Copy { to, .. } => to.exists(),
+            FsOp::Trash { path } | FsOp::Delete { path } => !path.exists(),
+            FsOp::Overwrite { path, id } => file_id(path) != Some(*id),
+        }
+    }
+
+    /// [`FsOp::is_applied`] for each of `ops`. The operation after an [`FsOp::Overwrite`]
+    /// targets a path that already exists, it only counts once the old one is gone.
+    pub fn applied(ops: &[FsOp]) -> Vec<bool> {
+        let mut applied: Vec<bool> = ops.iter().map(FsOp::is_applied).collect();
+        for i in 1..ops.len() {
+            if let FsOp::Overwrite { .. } = ops[i - 1] {
+                applied[i] &= applied[i - 1];
+            }
+        }
+        applied
+    }
+
+    /// Reverse the operation on disk
+    pub fn undo(&self) -> Res<()> {
+        match self {
+            FsOp::Mkdir { path } => fs::remove_dir(path)
+                .with_context(|| format!("Failed to remove directory '{}'", path.display())),
+            FsOp::Move { from, to } | FsOp::Rename { from, to } => {
+                if from.exists() {
+                    bail!("'{}' already exists.", from.display());
+                }
+                // 合并时源目录已经被删掉了
+                if let Some(parent) = from.parent() {
+                    fs::create_dir_all(parent).with_context(|| {
+                        format!("Failed to create directory '{}'", parent.display())
+                    })?;
+                }
+                file_ops::move_item(to, from, &mut |_| {}, None).with_context(|| {
+                    format!(
+                        "Failed to move '{}' back to '{}'",
+                        to.display(),
+                        from.display()
+                    )
+                })
+            }
+            FsOp::Copy { to: path, .. } | FsOp::Create { path } => file_ops::delete(&[path])
+                .with_context(|| format!("Failed to remove '{}'", path.display())),
+            FsOp::Trash { path } | FsOp::Overwrite { path, .. } => restore_from_trash(path),
+            FsOp::Delete { path } => bail!("'{}' was deleted permanently.", path.display()),
+        }
+    }
+}
+
+impl std::fmt::Display for FsOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FsOp::Mkdir { path } => write!(f, "mkdir {}", path.display()),
+            FsOp::Move { from, to } => write!(f, "move {} -> {}", from.display(), to.display()),
+            FsOp::Copy { from, to } => write!(f, "copy {} -> {}", from.display(), to.display()),
+            FsOp::Rename { from, to } => {
+                write!(f, "rename {} -> {}", from.display(), to.display())
+            }
+            FsOp::Trash { path } => write!(f, "trash {}", path.display()),
+            FsOp::Delete { path } => write!(f, "delete {}", path.display()),
+            FsOp::Overwrite { path, .. } => write!(f, "overwrite {}", path.display()),
+            FsOp::Create { path } => write!(f, "create {}", path.display()),
+        }
+    }
+}
+
+/// Identifies what is at `path`: its inode, or its modification time where there are none
+fn file_id(path: &Path) -> Option<u64> {
+    let meta = path.symlink_metadata().ok()?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt as _;
+        Some(meta.ino())
+    }
+    #[cfg(not(unix))]
+    {
+        let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(modified.as_nanos() as u64)
+    }
+}
+
+#[cfg(any(
+    target_os = "windows",
+    all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    )
+))]
+fn restore_from_trash(path: &Path) -> Res<()> {
+    // 同一路径可能被多次删除，取最近的那个
+    let item = trash::os_limited::list()?
+        .into_iter()
+        .filter(|item| item.original_path() == path)
+        .max_by_key(|item| item.time_deleted)
+        .ok_or_else(|| {
+            color_eyre::eyre::eyre!("'{}' is no longer in the trash.", path.display())
+        })?;
+    trash::os_limited::restore_all([item])
+        .with_context(|| format!("Failed to restore '{}' from trash", path.display()))
+}
+
+#[cfg(not(any(
+    target_os = "windows",
+    all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    )
+)))]
+fn restore_from_trash(path: &Path) -> Res<()> {
+    bail!(
+        "Restoring '{}' from trash is not supported on this platform.",
+        path.display()
+    )
+}
+
+/// One journal line
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Record {
+    /// unix time in millis when the batch started, also used as its id
+    pub batch: u64,
+    pub ops: Vec<FsOp>,
+}
+
+/// Result of undoing one batch
+#[derive(Debug, Default)]
+pub struct UndoReport {
+    pub batch: u64,
+    pub undone: Vec<FsOp>,
+    pub failed: Vec<(FsOp, String)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Journal {
+    path: PathBuf,
+}
+
+impl Journal {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// `journal.jsonl` in the state dir, None if it cannot be determined
+    pub fn open_default() -> Option<Self> {
+        crate::core::config::state_dir().map(|dir| Self::new(dir.join("journal.jsonl")))
+    }
+
+    /// A new batch id based on the current time
+    pub fn new_batch() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default()
+    }
+
+    /// Append the applied part of `ops` to the journal
+    pub fn record(&self, batch: u64, ops: Vec<FsOp>) -> Res<()> {
+        let applied = FsOp::applied(&ops);
+        let ops: Vec<FsOp> = ops
+            .into_iter()
+            .zip(applied)
+            .filter_map(|(op, applied)| applied.then_some(op))
+            .collect();
+        if ops.is_empty() {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut line = serde_json::to_string(&Record { batch, ops })?;
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut f| f.write_all(line.as_bytes()))
+            .with_context(|| format!("Failed to write journal '{}'", self.path.display()))
+    }
+
+    pub fn records(&self) -> Res<Vec<Record>> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+        let content = fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read journal '{}'", self.path.display()))?;
+        content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(i, line)| {
+                serde_json::from_str(line).with_context(|| {
+                    format!(
+                        "Corrupted journal '{}' at line {}",
+                        self.path.display(),
+                        i + 1
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// The operations of the last batch in execution order, without touching anything
+    pub fn last_batch(&self) -> Res<Option<(u64, Vec<FsOp>)>> {
+        let records = self.records()?;
+        let Some(batch) = records.last().map(|r| r.batch) else {
+            return Ok(None);
+        };
+        let ops = records
+            .into_iter()
+            .filter(|r| r.batch == batch)
+            .flat_map(|r| r.ops)
+            .collect();
+        Ok(Some((batch, ops)))
+    }
+
+    /// Reverse the last batch in reverse order and drop it from the journal.
+    /// Operations that fail are reported but not retried later.
+    pub fn undo_last(&self) -> Res<Option<UndoReport>> {
+        let Some((batch, ops)) = self.last_batch()? else {
+            return Ok(None);
+        };
+
+        let mut report = UndoReport {
+            batch,
+            ..Default::default()
+        };
+        for op in ops.into_iter().rev() {
+            match op.undo() {
+                Ok(()) => report.undone.push(op),
+                Err(e) => report.failed.push((op, format!("{:#}", e))),
+            }
+        }
+
+        let rest: Vec<Record> = self
+            .records()?
+            .into_iter()
+            .filter(|r| r.batch != batch)
+            .collect();
+        let mut content = String::new();
+        for record in &rest {
+            content.push_str(&serde_json::to_string(record)?);
+            content.push('\n');
+        }
+        fs::write(&self.path, content)
+            .with_context(|| format!("Failed to write journal '{}'", self.path.display()))?;
+
+        Ok(Some(report))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_undo_organize_and_copy() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let journal = Journal::new(dir.path().join("state/journal.jsonl"));
+        let a = dir.path().join("a.txt");
+        let b = dir.path().join("b.txt");
+        fs::write(&a, "a")?;
+        fs::write(&b, "b")?;
+
+        let batch = Journal::new_batch();
+        let organize = Cmd::Organize(
+            vec![a.clone()],
+            dir.path().join("sorted"),
+            ConflictPolicy::Ask,
+        );
+        let ops = FsOp::plan(&organize);
+        file_ops::organize(
+            &[&a],
+            &dir.path().join("sorted"),
+            ConflictPolicy::Ask,
+            None,
+            None,
+        )?;
+        journal.record(batch, ops)?;
+
+        let copy = Cmd::Copy(
+            vec![b.clone()],
+            dir.path().join("copies"),
+            ConflictPolicy::Ask,
+        );
+        let ops = FsOp::plan(&copy);
+        file_ops::copy(
+            std::slice::from_ref(&b),
+            dir.path().join("copies"),
+            ConflictPolicy::Ask,
+            None,
+            None,
+        )?;
+        journal.record(batch, ops)?;
+        assert_eq!(journal.records()?.len(), 2);
+
+        let report = journal.undo_last()?.unwrap();
+        assert_eq!(report.batch, batch);
+        assert!(report.failed.is_empty(), "{:?}", report.failed);
+        assert_eq!(fs::read_to_string(&a)?, "a");
+        assert!(!dir.path().join("sorted").exists());
+        assert!(!dir.path().join("copies").exists());
+        assert!(b.exists());
+        assert!(journal.records()?.is_empty());
+        assert!(journal.undo_last()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_overwrite() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let journal = Journal::new(dir.path().join("journal.jsonl"));
+        let a = dir.path().join("a.txt");
+        let dst = dir.path().join("dst");
+        fs::create_dir_all(&dst)?;
+        fs::write(&a, "new")?;
+        fs::write(dst.join("a.txt"), "old")?;
+
+        let cmd = Cmd::Copy(vec![a.clone()], dst.clone(), ConflictPolicy::Overwrite);
+        let ops = FsOp::plan(&cmd);
+        let old = dst.join("a.txt");
+        assert!(matches!(
+            &ops[..],
+            [FsOp::Overwrite { path, .. }, FsOp::Copy { .. }] if *path == old
+        ));
+        // 旧的还在，都不算做了
+        assert_eq!(FsOp::applied(&ops), [false, false]);
+        file_ops::copy(&[&a], &dst, ConflictPolicy::Overwrite, None, None)?;
+        assert_eq!(FsOp::applied(&ops), [true, true]);
+        journal.record(1, ops)?;
+
+        let report = journal.undo_last()?.unwrap();
+        assert!(report.failed.is_empty(), "{:?}", report.failed);
+        assert_eq!(fs::read_to_string(dst.join("a.txt"))?, "old");
+        assert_eq!(fs::read_to_string(&a)?, "new");
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_merge() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let journal = Journal::new(dir.path().join("journal.jsonl"));
+        let src = dir.path().join("photos");
+        let dst = dir.path().join("dst");
+        fs::create_dir_all(src.join("2024"))?;
+        fs::write(src.join("2024").join("a.jpg"), "a")?;
+        fs::create_dir_all(dst.join("photos").join("2024"))?;
+        fs::write(dst.join("photos").join("2024").join("old.jpg"), "old")?;
+
+        let cmd = Cmd::Move(vec![src.clone()], dst.clone(), ConflictPolicy::Merge);
+        let ops = FsOp::plan(&cmd);
+        assert_eq!(
+            ops,
+            [FsOp::Move {
+                from: src.join("2024").join("a.jpg"),
+                to: dst.join("photos").join("2024").join("a.jpg"),
+            }]
+        );
+        file_ops::move_into(&[&src], &dst, ConflictPolicy::Merge, None, None)?;
+        journal.record(1, ops)?;
+        assert!(!src.exists());
+
+        let report = journal.undo_last()?.unwrap();
+        assert!(report.failed.is_empty(), "{:?}", report.failed);
+        assert_eq!(fs::read_to_string(src.join("2024").join("a.jpg"))?, "a");
+        assert!(dst.join("photos").join("2024").join("old.jpg").exists());
+        assert!(!dst.join("photos").join("2024").join("a.jpg").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_extract() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let journal = Journal::new(dir.path().join("journal.jsonl"));
+        let photos = dir.path().join("photos");
+        fs::create_dir_all(photos.join("2024"))?;
+        fs::write(photos.join("2024").join("a.jpg"), "a")?;
+        fs::write(dir.path().join("b.txt"), "b")?;
+        let zip = dir.path().join("x.zip");
+        archive::archive(&[&photos, &dir.path().join("b.txt")], &zip, None)?;
+
+        // a new destination goes away entirely
+        let dst = dir.path().join("out");
+        let cmd = Cmd::AsyncExtract(0, zip.clone(), dst.clone());
+        let ops = FsOp::plan(&cmd);
+        archive::extract(&zip, &dst, None)?;
+        journal.record(1, ops)?;
+        let report = journal.undo_last()?.unwrap();
+        assert!(report.failed.is_empty(), "{:?}", report.failed);
+        assert!(!dst.exists());
+
+        // an existing one keeps what was there
+        fs::create_dir_all(dst.join("photos"))?;
+        fs::write(dst.join("photos").join("old.jpg"), "old")?;
+        let ops = FsOp::plan(&cmd);
+        assert!(!ops.contains(&FsOp::Mkdir {
+            path: dst.join("photos")
+        }));
+        archive::extract(&zip, &dst, None)?;
+        journal.record(2, ops)?;
+        let report = journal.undo_last()?.unwrap();
+        assert!(report.failed.is_empty(), "{:?}", report.failed);
+        assert!(dst.join("photos").join("old.jpg").exists());
+        assert!(!dst.join("photos").join("2024").exists());
+        assert!(!dst.join("b.txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_only_applied_ops_are_recorded() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let journal = Journal::new(dir.path().join("journal.jsonl"));
+        let a = dir.path().join("a.txt");
+        fs::write(&a, "a")?;
+
+        // nothing happened on disk
+        journal.record(
+            1,
+            FsOp::plan(&Cmd::Move(
+                vec![a.clone()],
+                dir.path().join("x"),
+                ConflictPolicy::Ask,
+            )),
+        )?;
+        assert!(journal.records()?.is_empty());
+
+        fs::rename(&a, dir.path().join("b.txt"))?;
+        journal.record(
+            2,
+            vec![
+                FsOp::Rename {
+                    from: a.clone(),
+                    to: dir.path().join("b.txt"),
+                },
+                FsOp::Delete {
+                    path: dir.path().join("b.txt"),
+                },
+            ],
+        )?;
+        assert_eq!(journal.last_batch()?.unwrap().1.len(), 1);
+
+        let report = journal.undo_last()?.unwrap();
+        assert_eq!(report.undone.len(), 1);
+        assert!(a.exists());
+        Ok(())
+    }
+}
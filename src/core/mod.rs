@@ -6,4 +6,9 @@ pub mod msg;
 pub mod runner;
 pub mod service;
 pub mod file_ops;
-pub mod fop;
\ No newline at end of file
+pub mod fop;
+pub mod journal;
+pub mod paths;
+pub mod plan;
+pub mod recent;
+pub mod report;
\ No newline at end of file
@@ -23,6 +23,14 @@ pub struct FileItem {
 }
 
 impl FileItem {
+    /// The last component of the path, `display_name` may be a relative path in a recursive view
+    pub fn file_name(&self) -> String {
+        self.path.file_name().map_or_else(
+            || self.display_name.clone(),
+            |n| n.to_string_lossy().to_string(),
+        )
+    }
+
     pub fn as_line(&self) -> Line<'static> {
         if self.is_dir {
             Line::from(format!("{}/", &self.display_name).blue())
@@ -100,13 +108,11 @@ impl<T> History<T> {
         Self::default()
     }
 
+    /// Log a new command, dropping the commands that could have been redone
     pub fn log(&mut self, cmd: T) {
-        if self.history.len() <= self.top {
-            self.history.push(cmd);
-        } else {
-            self.history[self.top] = cmd;
-        }
-        self.top = self.top.saturating_add(1);
+        self.history.truncate(self.top);
+        self.history.push(cmd);
+        self.top = self.history.len();
     }
 
     pub fn undo(&mut self) {
@@ -114,11 +120,16 @@ impl<T> History<T> {
     }
 
     pub fn redo(&mut self) {
-        if self.top < self.history.len() - 1 {
+        if self.top < self.history.len() {
             self.top = self.top.saturating_add(1);
         }
     }
 
+    /// The command that `redo` would re-apply
+    pub fn next(&self) -> Option<&T> {
+        self.history.get(self.top)
+    }
+
     pub fn last(&self) -> Option<&T> {
         if self.top == 0 {
             None
@@ -212,3 +223,40 @@ impl Cursor {
         Self { focus }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_history_undo_redo() {
+        let mut history = History::new();
+        history.redo();
+        history.undo();
+        assert_eq!(history.last(), None);
+        assert_eq!(history.next(), None);
+
+        (0..5).for_each(|i| history.log(i));
+        assert_eq!(history.last(), Some(&4));
+        history.redo();
+        assert_eq!(history.last(), Some(&4));
+
+        history.undo();
+        history.undo();
+        assert_eq!(history.last(), Some(&2));
+        assert_eq!(history.next(), Some(&3));
+        history.redo();
+        assert_eq!(history.last(), Some(&3));
+
+        // a new command drops the redo branch
+        history.log(10);
+        assert_eq!(history.next(), None);
+        assert_eq!(history.count(), 5);
+        history.redo();
+        assert_eq!(history.last(), Some(&10));
+
+        (0..10).for_each(|_| history.undo());
+        assert_eq!(history.last(), None);
+        assert_eq!(history.next(), Some(&0));
+    }
+}
@@ -1,14 +1,26 @@
 use core::fmt;
 
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use ratatui::{
     style::{Color, Style},
     widgets::Paragraph,
 };
+use unicode_width::UnicodeWidthChar as _;
 
 #[derive(Debug, Default, Clone)]
 pub struct InputBox {
     input: String,
     char_index: usize,
+    /// the dropdown of candidates, closed by any edit
+    completion: Option<Completion>,
+}
+
+/// Candidates offered for the input, see [`InputBox::complete`]
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Completion {
+    pub candidates: Vec<String>,
+    /// the candidate put into the input, None before cycling
+    pub selected: Option<usize>,
 }
 
 impl InputBox {
@@ -17,30 +29,124 @@ impl InputBox {
     }
 
     fn byte_index(&self) -> usize {
+        self.byte_index_of(self.char_index)
+    }
+
+    /// Byte index of the char at `char_index`, or the end
+    fn byte_index_of(&self, char_index: usize) -> usize {
         self.input
             .char_indices()
             .map(|(i, _)| i)
-            .nth(self.char_index)
+            .nth(char_index)
             .unwrap_or(self.input.len())
     }
 
-    fn move_cursor_left(&mut self) {
+    /// The cursor, counted in chars
+    pub fn char_index(&self) -> usize {
+        self.char_index
+    }
+
+    /// Display width of the text before the cursor, wide chars like `中` take 2 columns
+    pub fn cursor_width(&self) -> usize {
+        self.input
+            .chars()
+            .take(self.char_index())
+            .map(|c| c.width().unwrap_or(0))
+            .sum()
+    }
+
+    pub fn move_cursor_left(&mut self) {
         let cursor_moved_left = self.char_index.saturating_sub(1);
         self.char_index = self.clamp_cursor(cursor_moved_left);
     }
 
-    fn move_cursor_right(&mut self) {
+    pub fn move_cursor_right(&mut self) {
         let cursor_moved_right = self.char_index.saturating_add(1);
         self.char_index = self.clamp_cursor(cursor_moved_right);
     }
 
+    pub fn move_cursor_home(&mut self) {
+        self.char_index = 0;
+    }
+
+    pub fn move_cursor_end(&mut self) {
+        self.char_index = self.input.chars().count();
+    }
+
+    /// Remove the chars in `from..self.char_index` and put the cursor at `from`
+    fn delete_before(&mut self, from: usize) {
+        self.completion = None;
+        let range = self.byte_index_of(from)..self.byte_index();
+        self.input.replace_range(range, "");
+        self.char_index = from;
+    }
+
     pub fn enter_char(&mut self, new_char: char) {
+        self.completion = None;
         let index = self.byte_index();
         self.input.insert(index, new_char);
         self.move_cursor_right();
     }
 
+    /// Insert pasted text at the cursor, line breaks and other control chars are dropped
+    pub fn insert_str(&mut self, text: &str) {
+        self.completion = None;
+        let text: String = text.chars().filter(|c| !c.is_control()).collect();
+        let index = self.byte_index();
+        self.input.insert_str(index, &text);
+        self.char_index += text.chars().count();
+    }
+
+    /// Delete the char under the cursor, like the Delete key
+    pub fn delete_char_forward(&mut self) {
+        self.completion = None;
+        let index = self.byte_index();
+        if let Some(c) = self.input[index..].chars().next() {
+            self.input.replace_range(index..index + c.len_utf8(), "");
+        }
+    }
+
+    /// Delete the word before the cursor, like Ctrl+W.
+    /// Whitespace and path separators end a word, so `a/b/c/` becomes `a/b/`.
+    pub fn delete_word(&mut self) {
+        let is_boundary = |c: &char| c.is_whitespace() || std::path::is_separator(*c);
+        let before: Vec<char> = self.input.chars().take(self.char_index).collect();
+        let boundaries = before.iter().rev().take_while(|c| is_boundary(c)).count();
+        let word = before
+            .iter()
+            .rev()
+            .skip(boundaries)
+            .take_while(|c| !is_boundary(c))
+            .count();
+        self.delete_before(self.char_index - boundaries - word);
+    }
+
+    /// Delete everything before the cursor, like Ctrl+U
+    pub fn delete_to_start(&mut self) {
+        self.delete_before(0);
+    }
+
+    /// Line editing keys, false if `key_event` is not one of them.
+    /// Typing and Backspace are left to the caller.
+    pub fn handle_edit_key(&mut self, key_event: &KeyEvent) -> bool {
+        let ctrl = key_event.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key_event.modifiers.contains(KeyModifiers::ALT);
+        match key_event.code {
+            KeyCode::Left => self.move_cursor_left(),
+            KeyCode::Right => self.move_cursor_right(),
+            KeyCode::Home => self.move_cursor_home(),
+            KeyCode::End => self.move_cursor_end(),
+            KeyCode::Delete => self.delete_char_forward(),
+            KeyCode::Char('w') if ctrl => self.delete_word(),
+            KeyCode::Backspace if alt => self.delete_word(),
+            KeyCode::Char('u') if ctrl => self.delete_to_start(),
+            _ => return false,
+        }
+        true
+    }
+
     pub fn delete_char(&mut self) {
+        self.completion = None;
         if self.char_index > 0 {
             let byte_idx = self.byte_index();
             let prev_byte_idx = self
@@ -63,18 +169,204 @@ impl InputBox {
         &self.input
     }
 
-    pub fn as_paragraph(&'_ self, is_editing: bool) -> Paragraph<'_> {
-        Paragraph::new(self.input.as_str()).style(if is_editing {
-            Style::default().fg(Color::Yellow)
-        } else {
-            Style::default()
-        })
-        // .block(Block::bordered().title("Input"))
+    /// Replace the input, with the cursor at the end
+    fn set_input(&mut self, input: String) {
+        self.char_index = input.chars().count();
+        self.input = input;
+    }
+
+    pub fn completion(&self) -> Option<&Completion> {
+        self.completion.as_ref()
+    }
+
+    /// Offer `candidates` for the input: a single one is taken right away,
+    /// several fill in their common prefix and open the dropdown, see [`Self::cycle_completion`].
+    pub fn complete(&mut self, candidates: Vec<String>) {
+        match candidates.as_slice() {
+            [] => self.completion = None,
+            [only] => {
+                self.set_input(only.clone());
+                self.completion = None;
+            }
+            _ => {
+                let common = common_prefix(&candidates);
+                if common.len() > self.input.len() && common.starts_with(&self.input) {
+                    self.set_input(common);
+                }
+                self.completion = Some(Completion {
+                    candidates,
+                    selected: None,
+                });
+            }
+        }
+    }
+
+    /// Put the next, or previous, candidate of the open dropdown into the input
+    pub fn cycle_completion(&mut self, forward: bool) {
+        let Some(completion) = &mut self.completion else {
+            return;
+        };
+        let len = completion.candidates.len();
+        let next = match (completion.selected, forward) {
+            (None, true) => 0,
+            (None, false) => len - 1,
+            (Some(i), true) => (i + 1) % len,
+            (Some(i), false) => (i + len - 1) % len,
+        };
+        completion.selected = Some(next);
+        let candidate = completion.candidates[next].clone();
+        self.set_input(candidate);
+    }
+
+    /// Close the dropdown and keep the input, false if it was not open
+    pub fn accept_completion(&mut self) -> bool {
+        self.completion.take().is_some()
+    }
+
+    /// Columns to scroll so that the cursor stays visible in `width` columns
+    pub fn scroll_for(&self, width: u16) -> u16 {
+        (self.cursor_width() as u16).saturating_sub(width.saturating_sub(1))
+    }
+
+    /// Scrolled to keep the cursor visible in `width` columns, see [`Self::scroll_for`]
+    pub fn as_paragraph(&'_ self, is_editing: bool, width: u16) -> Paragraph<'_> {
+        Paragraph::new(self.input.as_str())
+            .style(if is_editing {
+                Style::default().fg(Color::Yellow)
+            } else {
+                Style::default()
+            })
+            .scroll((0, self.scroll_for(width)))
     }
 }
 
+/// The cursor is put at the end
+impl From<&str> for InputBox {
+    fn from(input: &str) -> Self {
+        Self {
+            input: input.to_string(),
+            char_index: input.chars().count(),
+            completion: None,
+        }
+    }
+}
+
+/// The longest common prefix, on char boundaries
+fn common_prefix(strings: &[String]) -> String {
+    let Some((first, rest)) = strings.split_first() else {
+        return String::new();
+    };
+    let len = rest.iter().fold(first.len(), |len, s| {
+        first[..len]
+            .char_indices()
+            .zip(s.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(len.min(s.len()), |((i, _), _)| i)
+    });
+    first[..len].to_string()
+}
+
 impl fmt::Display for InputBox {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.input)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn candidates(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_complete_and_cycle() {
+        let mut input = InputBox::from("ph");
+        input.complete(candidates(&["photos/", "photos-old/", "phone/"]));
+        // 公共前缀
+        assert_eq!(input.input(), "pho");
+        assert!(input.completion().is_some());
+
+        input.cycle_completion(true);
+        assert_eq!(input.input(), "photos/");
+        input.cycle_completion(true);
+        input.cycle_completion(true);
+        assert_eq!(input.input(), "phone/");
+        input.cycle_completion(true);
+        assert_eq!(input.input(), "photos/");
+        input.cycle_completion(false);
+        assert_eq!(input.input(), "phone/");
+
+        assert!(input.accept_completion());
+        assert!(!input.accept_completion());
+        assert_eq!(input.input(), "phone/");
+    }
+
+    #[test]
+    fn test_complete_single_and_edit() {
+        let mut input = InputBox::from("do");
+        input.complete(candidates(&["docs/"]));
+        assert_eq!(input.input(), "docs/");
+        assert!(input.completion().is_none());
+
+        input.complete(candidates(&["docs/a/", "docs/b/"]));
+        assert!(input.completion().is_some());
+        input.enter_char('a');
+        assert!(input.completion().is_none());
+        assert_eq!(input.input(), "docs/a");
+    }
+
+    #[test]
+    fn test_line_editing() {
+        let mut input = InputBox::from("ab/cd");
+        input.move_cursor_home();
+        input.delete_char();
+        input.move_cursor_right();
+        input.delete_char_forward();
+        assert_eq!(input.input(), "a/cd");
+        assert_eq!(input.char_index(), 1);
+
+        input.insert_str("x\ny\r\n");
+        assert_eq!(input.input(), "axy/cd");
+        assert_eq!(input.char_index(), 3);
+
+        input.move_cursor_end();
+        input.delete_char_forward();
+        assert_eq!(input.char_index(), 6);
+        input.delete_word();
+        assert_eq!(input.input(), "axy/");
+        input.enter_char(' ');
+        input.delete_word();
+        assert_eq!(input.input(), "");
+
+        let mut input = InputBox::from("日本 語/文件");
+        input.move_cursor_left();
+        input.move_cursor_left();
+        input.delete_to_start();
+        assert_eq!(input.input(), "文件");
+        assert_eq!(input.char_index(), 0);
+    }
+
+    #[test]
+    fn test_cursor_width() {
+        let mut input = InputBox::from("照片/😀a");
+        // 中文和 emoji 占两列
+        assert_eq!(input.cursor_width(), 2 + 2 + 1 + 2 + 1);
+        input.move_cursor_left();
+        input.move_cursor_left();
+        assert_eq!(input.cursor_width(), 5);
+        assert_eq!(input.scroll_for(4), 2);
+        assert_eq!(input.scroll_for(10), 0);
+        input.move_cursor_home();
+        assert_eq!(input.cursor_width(), 0);
+    }
+
+    #[test]
+    fn test_common_prefix() {
+        assert_eq!(common_prefix(&candidates(&["日本語/", "日本/"])), "日本");
+        assert_eq!(common_prefix(&candidates(&["abc", "ab"])), "ab");
+        assert_eq!(common_prefix(&candidates(&["x", "y"])), "");
+        assert_eq!(common_prefix(&[]), "");
+    }
+}
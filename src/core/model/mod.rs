@@ -1,6 +1,7 @@
 // pub mod models;
 pub mod component;
 pub mod processor;
+pub mod results;
 pub mod selector;
 pub mod home;
 
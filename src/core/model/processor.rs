@@ -1,22 +1,36 @@
 use crate::core::{
     cmd::Cmd,
+    file_ops::{Conflict, ConflictPolicy},
+    fop::{
+        archive,
+        rename::{self, RenameRule},
+    },
+    journal::Journal,
     model::{
         Model,
-        component::{self, ScrollList, input::InputBox},
+        component::{
+            self, FileItem, ScrollList,
+            input::{Completion, InputBox},
+        },
         selector::SelectModel,
     },
-    msg::Msg,
+    msg::{Msg, TaskStatus},
+    paths,
+    plan::{Plan, PlanAction, PlanSide},
+    recent::RecentDirs,
+    report::DryRunReport,
 };
 use color_eyre::Result as Res;
-use crossterm::event::{KeyCode, KeyEvent};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use ratatui::{
-    layout::{Layout, Position},
+    buffer::Buffer,
+    layout::{Constraint, Layout, Position, Rect},
     macros::constraints,
-    style::{Style, Stylize as _},
+    style::{Color, Style, Stylize as _},
     text::Line,
-    widgets::{Block, Clear, Widget as _},
+    widgets::{Block, Clear, Gauge, Paragraph, Widget as _},
 };
-use std::{fmt::Write, path::PathBuf};
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Default)]
 pub enum InProcess {
@@ -34,36 +48,97 @@ pub enum InProcess {
     Move(InputBox),
     /// alias 'c' 'cp'
     Copy(InputBox),
-    //s not yet impl
-    Zip,
-    /// not yet impl
+    /// alias 'z', takes the archive path, see [`crate::core::fop::archive::ArchiveFormat`]
+    Zip(InputBox),
+    /// alias 'x', takes an optional destination,
+    /// by default every archive goes into a folder named after it
+    Extract(InputBox),
+    /// alias 'r', takes a rename rule, see [`RenameRule`]
     Rename(InputBox),
 }
 
 impl InProcess {
-    pub fn len(&self) -> usize {
-        let mut counter = ByteCounter::new();
-        write!(&mut counter, "{}", self).expect("internal logic error");
-        counter.count
+    /// The name without the input, shown in the panel title
+    pub fn name(&self) -> &'static str {
+        use InProcess::*;
+        match self {
+            Waiting => "Waiting",
+            None => "None",
+            Delete => "Delete",
+            Trash => "Trash",
+            Organize(_) => "Organize",
+            Move(_) => "Move",
+            Copy(_) => "Copy",
+            Zip(_) => "Zip",
+            Extract(_) => "Extract",
+            Rename(_) => "Rename",
+        }
+    }
+
+    /// What the input box takes, shown as its title
+    fn input_hint(&self) -> &'static str {
+        use InProcess::*;
+        match self {
+            Organize(_) => "new directory",
+            Move(_) | Copy(_) => "destination",
+            Zip(_) => "archive path",
+            Extract(_) => "destination, empty for one folder per archive",
+            Rename(_) => "rename rule",
+            _ => "",
+        }
+    }
+
+    /// The input box of a process taking an argument
+    pub fn input_box(&self) -> Option<&InputBox> {
+        use InProcess::*;
+        match self {
+            Organize(x) | Move(x) | Copy(x) | Zip(x) | Extract(x) | Rename(x) => Some(x),
+            _ => Option::None,
+        }
+    }
+
+    pub fn input_box_mut(&mut self) -> Option<&mut InputBox> {
+        use InProcess::*;
+        match self {
+            Organize(x) | Move(x) | Copy(x) | Zip(x) | Extract(x) | Rename(x) => Some(x),
+            _ => Option::None,
+        }
     }
 
     pub fn try_enter(&mut self, ch: char) {
+        if let Some(x) = self.input_box_mut() {
+            x.enter_char(ch);
+        }
+    }
+
+    /// Whether it puts items into a directory, so that a [`ConflictPolicy`] applies
+    pub fn takes_policy(&self) -> bool {
+        matches!(
+            self,
+            InProcess::Organize(_) | InProcess::Move(_) | InProcess::Copy(_)
+        )
+    }
+
+    /// The input box of a process taking a path, which can be completed
+    pub fn path_input(&self) -> Option<&InputBox> {
         use InProcess::*;
         match self {
-            Organize(x) => x.enter_char(ch),
-            Move(x) => x.enter_char(ch),
-            Copy(x) => x.enter_char(ch),
-            _ => {}
+            Organize(x) | Move(x) | Copy(x) | Zip(x) | Extract(x) => Some(x),
+            _ => Option::None,
         }
     }
 
-    pub fn try_delete(&mut self) {
+    pub fn path_input_mut(&mut self) -> Option<&mut InputBox> {
         use InProcess::*;
         match self {
-            Organize(x) => x.delete_char(),
-            Move(x) => x.delete_char(),
-            Copy(x) => x.delete_char(),
-            _ => {}
+            Organize(x) | Move(x) | Copy(x) | Zip(x) | Extract(x) => Some(x),
+            _ => Option::None,
+        }
+    }
+
+    pub fn try_delete(&mut self) {
+        if let Some(x) = self.input_box_mut() {
+            x.delete_char();
         }
     }
 }
@@ -78,37 +153,90 @@ impl std::fmt::Display for InProcess {
             Organize(input) => write!(f, "Organize: {}", input),
             Move(input) => write!(f, "Move: {}", input),
             Copy(input) => write!(f, "Copy: {}", input),
+            Rename(input) => write!(f, "Rename: {}", input),
+            Zip(input) => write!(f, "Zip: {}", input),
+            Extract(input) if input.input().is_empty() => write!(f, "Extract"),
+            Extract(input) => write!(f, "Extract: {}", input),
             x => write!(f, "{:?}", x),
         }
     }
 }
 
-/// 一个简单的计数器，用于计算格式化后的字节长度而不分配内存
-struct ByteCounter {
-    count: usize,
+/// How a single item would be affected by the typed rename rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RenameStatus {
+    Renamed,
+    Unchanged,
+    /// the rule produced an empty or otherwise invalid name
+    Invalid,
+    /// another item gets the same name, or the target already exists on disk
+    Collision,
 }
 
-impl ByteCounter {
-    fn new() -> Self {
-        ByteCounter { count: 0 }
-    }
+/// One submitted task, shown as a gauge in the progress panel
+#[derive(Debug, Clone, PartialEq)]
+struct TaskEntry {
+    id: u64,
+    label: String,
+    status: TaskStatus,
 }
 
-/// 为 ByteCounter 实现 Write trait
-impl std::fmt::Write for ByteCounter {
-    fn write_str(&mut self, s: &str) -> std::fmt::Result {
-        // 累加传入字符串切片的字节长度
-        self.count += s.len();
-        Ok(())
+impl TaskEntry {
+    /// One entry per `Async*` command in `cmd`
+    fn collect(cmd: &Cmd, out: &mut Vec<TaskEntry>) {
+        let count = |items: &[PathBuf]| match items {
+            [item] => item.display().to_string(),
+            _ => format!("{} items", items.len()),
+        };
+        let label = match cmd {
+            Cmd::Seq(cmds) | Cmd::Batch(cmds) => {
+                return cmds.iter().for_each(|cmd| Self::collect(cmd, out));
+            }
+            Cmd::AsyncOrganize(_, items, to, _) => {
+                format!("Organize {} → {}", count(items), to.display())
+            }
+            Cmd::AsyncMove(_, items, to, _) => {
+                format!("Move {} → {}", count(items), to.display())
+            }
+            Cmd::AsyncCopy(_, items, to, _) => {
+                format!("Copy {} → {}", count(items), to.display())
+            }
+            Cmd::AsyncDelete(_, items) => format!("Delete {}", count(items)),
+            Cmd::AsyncTrash(_, items) => format!("Trash {}", count(items)),
+            Cmd::AsyncRename(_, pairs) => format!("Rename {} items", pairs.len()),
+            Cmd::AsyncZip(_, items, to) => format!("Zip {} → {}", count(items), to.display()),
+            Cmd::AsyncExtract(_, archive, to) => {
+                format!("Extract {} → {}", archive.display(), to.display())
+            }
+            _ => return,
+        };
+        out.extend(cmd.task_ids().into_iter().map(|id| TaskEntry {
+            id,
+            label: label.clone(),
+            status: TaskStatus::Pending,
+        }));
+    }
+
+    fn is_finished(&self) -> bool {
+        matches!(
+            self.status,
+            TaskStatus::Completed | TaskStatus::Failed(_) | TaskStatus::Cancelled
+        )
     }
 }
 
 #[derive(Debug, Default)]
 pub struct Processor {
+    /// the sorted directory, relative destinations start here
+    base: PathBuf,
+    /// the items left in the middle, only kept for [`Self::to_plan`]
+    pending: ScrollList,
     left: ScrollList,
     left_proc: InProcess,
+    left_policy: ConflictPolicy,
     right: ScrollList,
     right_proc: InProcess,
+    right_policy: ConflictPolicy,
 
     /// default false, which is left
     focus_right: bool,
@@ -117,20 +245,280 @@ pub struct Processor {
     is_editing: bool,
 
     double_check: bool,
+    /// shown with the confirmation, computed when it opens instead of on every frame
+    report: DryRunReport,
+    /// the side whose `Ask` policy has to be answered before confirming, true for right
+    asking: Option<bool>,
+    /// the existing targets of the side being asked, computed when the question opens
+    asked_targets: Vec<PathBuf>,
+
+    next_task_id: u64,
+    /// async tasks submitted on confirm, the processor exits once all of them are finished
+    tasks: Vec<TaskEntry>,
+    /// the right side, submitted once the tasks of the left one are finished
+    queued: Option<Cmd>,
+    /// index into `tasks` on the progress panel
+    selected_task: usize,
+
+    /// offered first when completing a destination
+    recent: Option<RecentDirs>,
 }
 
 impl Processor {
-    pub fn new(inner: SelectModel) -> Self {
+    /// Both sides start with the default `policy` from the config,
+    /// `recent` destinations are offered first when completing and updated on confirm
+    pub fn new(inner: SelectModel, policy: ConflictPolicy, recent: Option<RecentDirs>) -> Self {
         Self {
+            base: inner.path.clone(),
+            pending: inner.mid.clone(),
             left: inner.left.clone(),
             right: inner.right.clone(),
+            left_policy: policy,
+            right_policy: policy,
             focus_right: false,
+            recent,
             ..Default::default()
         }
     }
 
+    fn policy_mut(&mut self, right: bool) -> &mut ConflictPolicy {
+        if right {
+            &mut self.right_policy
+        } else {
+            &mut self.left_policy
+        }
+    }
+
+    /// The process, list and policy of one side, true for right
+    fn side(&self, right: bool) -> (&InProcess, &ScrollList, ConflictPolicy) {
+        if right {
+            (&self.right_proc, &self.right, self.right_policy)
+        } else {
+            (&self.left_proc, &self.left, self.left_policy)
+        }
+    }
+
+    /// The command of one side, see [`Self::proc_into_cmd`]
+    fn side_cmd(&self, right: bool, policy: ConflictPolicy, next_id: &mut u64) -> Cmd {
+        let (proc, list, _) = self.side(right);
+        Self::proc_into_cmd(proc, list, policy, &self.base, next_id)
+    }
+
+    /// What is being sorted as a [`Plan`], with the actions as typed so far.
+    /// Item paths are relative to the sorted directory if they are inside it
+    pub fn to_plan(&self) -> Plan {
+        let relative = |list: &ScrollList| {
+            list.items
+                .iter()
+                .map(|item| {
+                    item.path
+                        .strip_prefix(&self.base)
+                        .unwrap_or(&item.path)
+                        .to_path_buf()
+                })
+                .collect()
+        };
+        let side = |right| {
+            let (proc, list, policy) = self.side(right);
+            PlanSide {
+                action: PlanAction::from_proc(proc),
+                on_conflict: proc.takes_policy().then_some(policy),
+                items: relative(list),
+            }
+        };
+        Plan {
+            root: self.base.clone(),
+            pending: relative(&self.pending),
+            left: side(false),
+            right: side(true),
+        }
+    }
+
+    /// Targets of one side that already exist, as if its policy was `Ask`
+    fn existing_targets(&self, right: bool) -> Vec<PathBuf> {
+        if !self.side(right).0.takes_policy() {
+            return vec![];
+        }
+        let cmd = self.side_cmd(right, ConflictPolicy::Ask, &mut 0);
+        DryRunReport::of(&cmd)
+            .conflicts
+            .into_iter()
+            .filter_map(|c| match c {
+                Conflict::DestinationExists(path) => Some(path),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The first side that has to be asked for a policy, true for right, with its existing targets
+    fn side_to_ask(&self) -> Option<(bool, Vec<PathBuf>)> {
+        [false, true]
+            .into_iter()
+            .filter(|&right| self.side(right).2 == ConflictPolicy::Ask)
+            .map(|right| (right, self.existing_targets(right)))
+            .find(|(_, targets)| !targets.is_empty())
+    }
+
+    /// Organize gathers into a new directory, if it exists already the only answer is to merge
+    fn only_merge(&self, right: bool) -> bool {
+        match self.side(right).0 {
+            InProcess::Organize(to) => paths::resolve(&self.base, to.input()).is_dir(),
+            _ => false,
+        }
+    }
+
+    /// Answer the conflict question, then go on confirming
+    fn handle_ask_key(&mut self, right: bool, key_event: &KeyEvent) -> Res<Cmd> {
+        let only_merge = self.only_merge(right);
+        let policy = match key_event.code {
+            KeyCode::Char('m') => ConflictPolicy::Merge,
+            KeyCode::Char('s') if !only_merge => ConflictPolicy::Skip,
+            KeyCode::Char('o') if !only_merge => ConflictPolicy::Overwrite,
+            KeyCode::Char('r') if !only_merge => ConflictPolicy::Rename,
+            KeyCode::Esc => {
+                self.asking = None;
+                return Ok(Cmd::None);
+            }
+            _ => return Ok(Cmd::None),
+        };
+        *self.policy_mut(right) = policy;
+        self.asking = None;
+        self.confirm()
+    }
+
+    /// Completions of a typed path: recent directories under it first, then sub directories
+    fn path_candidates(&self, input: &str) -> Vec<String> {
+        let resolved = paths::resolve(&self.base, input);
+        let sep = std::path::MAIN_SEPARATOR;
+        let recent = self
+            .recent
+            .iter()
+            .flat_map(|r| r.entries())
+            .filter(|dir| dir.is_dir())
+            .filter(|dir| {
+                input.is_empty()
+                    || dir
+                        .to_string_lossy()
+                        .starts_with(&*resolved.to_string_lossy())
+            })
+            .map(|dir| format!("{}{}", dir.display(), sep));
+        let mut seen = std::collections::HashSet::new();
+        recent
+            .chain(paths::complete_dir(&self.base, input))
+            .filter(|c| seen.insert(paths::resolve(&self.base, c)))
+            .collect()
+    }
+
+    /// Complete the path being typed, or cycle the open dropdown
+    fn complete_path(&mut self, forward: bool) {
+        let Some(input) = self.curr_proc().path_input() else {
+            return;
+        };
+        let candidates =
+            (input.completion().is_none()).then(|| self.path_candidates(input.input()));
+        let Some(input) = self.curr_proc_mut().path_input_mut() else {
+            return;
+        };
+        match candidates {
+            Some(candidates) => input.complete(candidates),
+            Option::None => input.cycle_completion(forward),
+        }
+    }
+
+    /// Remember the destinations of both sides
+    fn remember_destinations(&mut self) {
+        let Some(recent) = &mut self.recent else {
+            return;
+        };
+        for proc in [&self.left_proc, &self.right_proc] {
+            if let InProcess::Organize(to) | InProcess::Move(to) | InProcess::Copy(to) = proc
+                && !to.input().is_empty()
+            {
+                recent.push(paths::resolve(&self.base, to.input()));
+            }
+        }
+        if let Err(e) = recent.save() {
+            tracing::error!("Failed to save recent dirs: {:?}", e);
+        }
+    }
+
+    /// While tasks run only the progress panel takes keys
+    fn handle_task_key(&mut self, key_event: &KeyEvent) -> Cmd {
+        match key_event.code {
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.selected_task = self.selected_task.saturating_sub(1);
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.selected_task = (self.selected_task + 1).min(self.tasks.len() - 1);
+            }
+            KeyCode::Char('c') => {
+                let Some(task) = self.tasks.get_mut(self.selected_task) else {
+                    return Cmd::None;
+                };
+                if task.is_finished() {
+                    return Cmd::None;
+                }
+                // 还没提交的直接从队列里去掉
+                let id = task.id;
+                match self.queued.take() {
+                    Some(queued) if queued.task_ids().contains(&id) => {
+                        task.status = TaskStatus::Cancelled;
+                        self.queued = Some(without_task(queued, id));
+                        return self.advance();
+                    }
+                    queued => self.queued = queued,
+                }
+                return Cmd::CancelTask(id);
+            }
+            _ => {}
+        }
+        Cmd::None
+    }
+
+    /// Once the submitted tasks are finished, submit the queued side or exit if there is none
+    fn advance(&mut self) -> Cmd {
+        let queued_ids = self.queued.as_ref().map(Cmd::task_ids).unwrap_or_default();
+        if self
+            .tasks
+            .iter()
+            .any(|t| !t.is_finished() && !queued_ids.contains(&t.id))
+        {
+            return Cmd::None;
+        }
+        match self.queued.take() {
+            Some(queued) if !queued_ids.is_empty() => queued,
+            Some(queued) => Cmd::Seq(vec![queued, Cmd::Exit]),
+            None => Cmd::Exit,
+        }
+    }
+
+    fn selected_task_id(&self) -> u64 {
+        self.tasks
+            .get(self.selected_task)
+            .map_or(u64::MAX, |task| task.id)
+    }
+
     fn handle_key_event(&mut self, key_event: &KeyEvent) -> Res<Cmd> {
+        if !self.tasks.is_empty() {
+            return Ok(self.handle_task_key(key_event));
+        }
+        if let Some(right) = self.asking {
+            return self.handle_ask_key(right, key_event);
+        }
+        if self.is_editing
+            && let Some(input) = self.curr_proc_mut().input_box_mut()
+            && input.handle_edit_key(key_event)
+        {
+            return Ok(Cmd::None);
+        }
         match key_event.code {
+            // 输入时也能切换
+            KeyCode::Char('p') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.cycle_policy();
+            }
+            KeyCode::Char('s') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Ok(Cmd::SavePlan(self.to_plan()));
+            }
             KeyCode::Left | KeyCode::Right => {
                 self.focus_right = !self.focus_right;
                 self.is_editing = false;
@@ -168,12 +556,26 @@ impl Processor {
                             self.is_editing = true;
                             *self.curr_proc_mut() = InProcess::Rename(Default::default());
                         }
+                        'z' => {
+                            self.is_editing = true;
+                            *self.curr_proc_mut() = InProcess::Zip(Default::default());
+                        }
+                        'x' => {
+                            self.is_editing = true;
+                            *self.curr_proc_mut() = InProcess::Extract(Default::default());
+                        }
+                        'p' => self.cycle_policy(),
                         _ => {
                             self.is_editing = false;
                         }
                     }
                 }
             }
+            // 编辑路径时用来补全
+            KeyCode::Tab if self.is_editing && self.curr_proc().path_input().is_some() => {
+                self.complete_path(true);
+            }
+            KeyCode::BackTab if self.is_editing => self.complete_path(false),
             KeyCode::Tab => {
                 self.focus_right = !self.focus_right;
                 self.is_editing = false;
@@ -184,16 +586,22 @@ impl Processor {
                 }
             }
             KeyCode::Enter => {
-                if self.double_check {
-                    return Ok(Cmd::Seq(vec![
-                        Cmd::Batch(vec![
-                            Self::proc_into_cmd(&self.left_proc, &self.left),
-                            Self::proc_into_cmd(&self.right_proc, &self.right),
-                        ]),
-                        Cmd::Exit,
-                    ]));
-                } else {
-                    self.double_check = true;
+                if self.is_editing
+                    && let Some(input) = self.curr_proc_mut().path_input_mut()
+                    && input.accept_completion()
+                {
+                    return Ok(Cmd::None);
+                }
+                return self.confirm();
+            }
+            // 先关掉候选，再结束编辑
+            KeyCode::Esc if self.is_editing => {
+                let closed = self
+                    .curr_proc_mut()
+                    .path_input_mut()
+                    .is_some_and(InputBox::accept_completion);
+                if !closed {
+                    self.is_editing = false;
                 }
             }
             KeyCode::Esc => {
@@ -208,24 +616,367 @@ impl Processor {
         Ok(Cmd::None)
     }
 
-    fn proc_into_cmd(proc: &InProcess, list: &ScrollList) -> Cmd {
+    fn cycle_policy(&mut self) {
+        let right = self.focus_right;
+        let policy = self.policy_mut(right);
+        *policy = policy.next();
+    }
+
+    /// The first Enter shows the confirmation, the second runs it.
+    /// A side with an `Ask` policy and existing targets is asked first.
+    fn confirm(&mut self) -> Res<Cmd> {
+        for (proc, list) in [
+            (&self.left_proc, &self.left),
+            (&self.right_proc, &self.right),
+        ] {
+            if let InProcess::Rename(input) = proc
+                && let Err(e) = Self::rename_targets(input.input(), list)
+            {
+                self.double_check = false;
+                return Ok(Cmd::Error(e));
+            }
+        }
+        if let Some((right, targets)) = self.side_to_ask() {
+            self.double_check = false;
+            self.asking = Some(right);
+            self.asked_targets = targets;
+            return Ok(Cmd::None);
+        }
+        if self.double_check {
+            let mut next_id = self.next_task_id;
+            let left = self.side_cmd(false, self.left_policy, &mut next_id);
+            let right = self.side_cmd(true, self.right_policy, &mut next_id);
+            self.next_task_id = next_id;
+            self.remember_destinations();
+            self.tasks.clear();
+            self.selected_task = 0;
+            TaskEntry::collect(&left, &mut self.tasks);
+            TaskEntry::collect(&right, &mut self.tasks);
+            // 右边晚些才提交，两边记在同一批里，一起撤销
+            let batch = Journal::new_batch();
+            let left = Cmd::InBatch(batch, Box::new(left));
+            let right = Cmd::InBatch(batch, Box::new(right));
+
+            self.double_check = false;
+            if self.tasks.is_empty() {
+                return Ok(Cmd::Seq(vec![left, right, Cmd::Exit]));
+            }
+            // wait for the async tasks before exiting, see `update`
+            if left.task_ids().is_empty() {
+                return Ok(Cmd::Seq(vec![left, right]));
+            }
+            // the sides may touch the same paths, so the right one waits for the left one
+            self.queued = Some(right);
+            return Ok(left);
+        }
+        self.double_check = true;
+        self.report = self.dry_run_report();
+        Ok(Cmd::None)
+    }
+
+    /// Every process runs as an async task, whose id is taken from `next_id`.
+    /// `policy` applies to the processes putting items into a directory.
+    /// Typed destinations are resolved from `base`, see [`paths::resolve`].
+    pub(crate) fn proc_into_cmd(
+        proc: &InProcess,
+        list: &ScrollList,
+        policy: ConflictPolicy,
+        base: &Path,
+        next_id: &mut u64,
+    ) -> Cmd {
+        let mut alloc_id = || {
+            let id = *next_id;
+            *next_id += 1;
+            id
+        };
+        let paths = || list.items.iter().map(|i| i.path.clone()).collect();
+        let dest = |to: &InputBox| paths::resolve(base, to.input());
         match proc {
             InProcess::None => Cmd::None,
-            InProcess::Delete => Cmd::Delete(list.items.iter().map(|f| f.path.clone()).collect()),
-            InProcess::Organize(to) => Cmd::Organize(
-                list.items.iter().map(|i| i.path.clone()).collect(),
-                PathBuf::from(to.input()),
+            InProcess::Delete => Cmd::AsyncDelete(alloc_id(), paths()),
+            InProcess::Organize(to) => Cmd::AsyncOrganize(alloc_id(), paths(), dest(to), policy),
+            InProcess::Copy(to) => Cmd::AsyncCopy(alloc_id(), paths(), dest(to), policy),
+            InProcess::Move(to) => Cmd::AsyncMove(alloc_id(), paths(), dest(to), policy),
+            InProcess::Trash => Cmd::AsyncTrash(alloc_id(), paths()),
+            InProcess::Rename(rule) => match Self::rename_targets(rule.input(), list) {
+                Ok(pairs) => Cmd::AsyncRename(alloc_id(), pairs),
+                Err(e) => Cmd::Error(e),
+            },
+            InProcess::Zip(to) => Cmd::AsyncZip(alloc_id(), paths(), dest(to)),
+            // one task per archive, so that each one fails on its own.
+            // Without a destination only what looks like an archive is extracted, next to it
+            InProcess::Extract(to) if to.input().is_empty() => {
+                let mut cmds = Vec::new();
+                let mut skipped = Vec::new();
+                for item in &list.items {
+                    match archive::default_extract_dir(&item.path) {
+                        Some(dest) => {
+                            cmds.push(Cmd::AsyncExtract(alloc_id(), item.path.clone(), dest))
+                        }
+                        None => skipped.push(item.display_name.as_str()),
+                    }
+                }
+                if !skipped.is_empty() {
+                    cmds.push(Cmd::Error(format!(
+                        "Not an archive, skipped: {}",
+                        skipped.join(", ")
+                    )));
+                }
+                Cmd::Batch(cmds)
+            }
+            InProcess::Extract(to) => Cmd::Batch(
+                list.items
+                    .iter()
+                    .map(|i| Cmd::AsyncExtract(alloc_id(), i.path.clone(), dest(to)))
+                    .collect(),
             ),
-            InProcess::Copy(to) => Cmd::Copy(
-                list.items.iter().map(|i| i.path.clone()).collect(),
-                PathBuf::from(to.input()),
+            _ => Cmd::None,
+        }
+    }
+
+    /// Compute the new name of every item in `list` together with its [`RenameStatus`],
+    /// used to preview a rename before confirming it.
+    fn rename_preview(
+        rule: &str,
+        list: &ScrollList,
+    ) -> Result<Vec<(String, RenameStatus)>, String> {
+        let rule: RenameRule = rule.parse()?;
+        let names = list.items.iter().map(FileItem::file_name).collect();
+        let new_names = rename::rename(names, rule).map_err(|e| e.to_string())?;
+
+        // unchanged items still occupy their name, so they are counted too.
+        // Items may come from different directories, only the same target path collides
+        let mut counts = std::collections::HashMap::new();
+        for (item, new_name) in list.items.iter().zip(&new_names) {
+            *counts
+                .entry(item.path.with_file_name(new_name))
+                .or_insert(0) += 1;
+        }
+
+        Ok(list
+            .items
+            .iter()
+            .zip(&new_names)
+            .map(|(item, new_name)| {
+                let status = if new_name.is_empty() || new_name.contains(std::path::is_separator) {
+                    RenameStatus::Invalid
+                } else if *new_name == item.file_name() {
+                    RenameStatus::Unchanged
+                } else if counts[&item.path.with_file_name(new_name)] > 1
+                    || item.path.with_file_name(new_name).exists()
+                {
+                    RenameStatus::Collision
+                } else {
+                    RenameStatus::Renamed
+                };
+                (new_name.clone(), status)
+            })
+            .collect())
+    }
+
+    /// One line per item in the form `old → new`, colored by [`RenameStatus`].
+    /// If the rule does not parse, the plain items are shown alongside the error.
+    fn rename_preview_lines(rule: &str, list: &ScrollList) -> (Vec<Line<'static>>, Option<String>) {
+        match Self::rename_preview(rule, list) {
+            Ok(preview) => {
+                let lines = list
+                    .items
+                    .iter()
+                    .zip(preview)
+                    .map(|(item, (new_name, status))| {
+                        let new_span = match status {
+                            RenameStatus::Renamed => new_name.green(),
+                            RenameStatus::Unchanged => new_name.dim(),
+                            RenameStatus::Invalid => "<invalid>".red().bold(),
+                            RenameStatus::Collision => new_name.red().bold(),
+                        };
+                        Line::from(vec![
+                            item.display_name.clone().into(),
+                            " → ".dim(),
+                            new_span,
+                        ])
+                    })
+                    .collect();
+                (lines, None)
+            }
+            Err(e) => (
+                list.items.iter().map(|i| i.as_line()).collect(),
+                // nothing typed yet is not worth an error
+                (!rule.trim().is_empty()).then_some(e),
             ),
-            InProcess::Move(to) => Cmd::Move(
-                list.items.iter().map(|i| i.path.clone()).collect(),
-                to.input().into(),
+        }
+    }
+
+    /// Short description of what one side will do, shown in the confirmation popup.
+    /// Destinations are shown resolved.
+    fn proc_summary(&self, right: bool) -> String {
+        let (proc, list, policy) = self.side(right);
+        let resolved = |to: &InputBox| paths::resolve(&self.base, to.input());
+        match proc {
+            InProcess::Organize(to) | InProcess::Move(to) | InProcess::Copy(to) => format!(
+                "{} → {} (on conflict: {})",
+                proc,
+                resolved(to).display(),
+                policy
             ),
-            InProcess::Trash => Cmd::Trash(list.items.iter().map(|i| i.path.clone()).collect()),
-            _ => Cmd::None,
+            InProcess::Zip(to) => format!("{} → {}", proc, resolved(to).display()),
+            InProcess::Extract(to) if !to.input().is_empty() => {
+                format!("{} → {}", proc, resolved(to).display())
+            }
+            InProcess::Extract(_) => {
+                let skipped = list
+                    .items
+                    .iter()
+                    .filter(|i| archive::default_extract_dir(&i.path).is_none())
+                    .count();
+                match skipped {
+                    0 => proc.to_string(),
+                    n => format!("{} ({} not archives, skipped)", proc, n),
+                }
+            }
+            InProcess::Rename(rule) => match Self::rename_preview(rule.input(), list) {
+                Ok(preview) => {
+                    let count = |s| preview.iter().filter(|(_, st)| *st == s).count();
+                    format!(
+                        "{} ({} renamed, {} unchanged, {} conflicts)",
+                        proc,
+                        count(RenameStatus::Renamed),
+                        count(RenameStatus::Unchanged),
+                        count(RenameStatus::Invalid) + count(RenameStatus::Collision),
+                    )
+                }
+                Err(e) => format!("{} ({})", proc, e),
+            },
+            _ => proc.to_string(),
+        }
+    }
+
+    /// Every filesystem mutation and predicted conflict of confirming now.
+    /// It walks the filesystem, so it is kept in `report` while the confirmation is open
+    fn dry_run_report(&self) -> DryRunReport {
+        let mut next_id = self.next_task_id;
+        let mut report = DryRunReport::new();
+        report.add(&self.side_cmd(false, self.left_policy, &mut next_id));
+        report.add(&self.side_cmd(true, self.right_policy, &mut next_id));
+        report
+    }
+
+    /// The kept [`Self::dry_run_report`], at most `max` lines
+    fn dry_run_lines(&self, max: usize) -> Vec<String> {
+        let mut lines = self.report.lines();
+        if lines.len() > max {
+            let hidden = lines.len() - max.saturating_sub(1);
+            lines.truncate(max.saturating_sub(1));
+            lines.push(format!("... and {} more", hidden));
+        }
+        lines
+    }
+
+    /// Pair every item path of `list` with its renamed sibling path.
+    /// Fails if any item would get an invalid or colliding name.
+    fn rename_targets(rule: &str, list: &ScrollList) -> Result<Vec<(PathBuf, PathBuf)>, String> {
+        let preview = Self::rename_preview(rule, list)?;
+
+        list.items
+            .iter()
+            .zip(preview)
+            .filter(|(_, (_, status))| *status != RenameStatus::Unchanged)
+            .map(|(item, (new_name, status))| match status {
+                RenameStatus::Invalid => Err(format!(
+                    "Invalid new name {:?} for '{}'",
+                    new_name, item.display_name
+                )),
+                RenameStatus::Collision => Err(format!(
+                    "'{}' would be renamed to '{}', which is already taken",
+                    item.display_name, new_name
+                )),
+                _ => Ok((item.path.clone(), item.path.with_file_name(new_name))),
+            })
+            .collect()
+    }
+
+    /// Ask how to handle the existing targets of one side
+    fn render_ask_popup(&self, right: bool, area: Rect, buf: &mut Buffer) {
+        let proc = self.side(right).0;
+        let side_name = if right { "right" } else { "left" };
+        let horizontal = Layout::horizontal(constraints![==10%, ==80%, ==10%]).split(area);
+        let popup_area =
+            Layout::vertical(constraints![==10%, ==80%, ==10%]).split(horizontal[1])[1];
+        Clear.render(popup_area, buf);
+
+        // 边框 2 行，标题和提示 4 行
+        let max_lines = (popup_area.height as usize).saturating_sub(6).max(1);
+        let targets = &self.asked_targets;
+        let mut lines: Vec<String> = targets
+            .iter()
+            .take(max_lines)
+            .map(|path| format!("  {}\n", path.display()))
+            .collect();
+        if targets.len() > max_lines {
+            lines.pop();
+            lines.push(format!(
+                "  ... and {} more\n",
+                targets.len() - max_lines + 1
+            ));
+        }
+        let answers = if self.only_merge(right) {
+            "[m]erge into the existing directory"
+        } else {
+            "[m]erge [s]kip [o]verwrite [r]ename"
+        };
+        let popup = component::popup::Popup::new(
+            "Conflict".into(),
+            format!(
+                "{} ({}) would overwrite:\n{}{}\n[esc] to cancel.",
+                proc,
+                side_name,
+                lines.concat(),
+                answers,
+            )
+            .into(),
+            Style::new().yellow(),
+            Style::new().white().bold(),
+            Style::new().yellow(),
+        );
+        popup.render(popup_area, buf);
+    }
+
+    /// One gauge per task, centered over `area`
+    fn render_progress_panel(&self, area: Rect, buf: &mut Buffer) {
+        let height = (self.tasks.len() as u16 + 2).min(area.height);
+        let width = (area.width * 4 / 5).max(area.width.min(40));
+        let panel = Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + (area.height - height) / 2,
+            width,
+            height,
+        };
+        Clear.render(panel, buf);
+        let block = Block::bordered()
+            .title(" Progress ".bold())
+            .border_style(Style::new().blue());
+        let inner = block.inner(panel);
+        block.render(panel, buf);
+
+        let rows = Layout::vertical(vec![Constraint::Length(1); self.tasks.len()]).split(inner);
+        for (task, row) in self.tasks.iter().zip(rows.iter()) {
+            let (ratio, state, color) = match &task.status {
+                TaskStatus::Pending => (0.0, "pending".to_string(), Color::DarkGray),
+                TaskStatus::Processing(p) => (p.ratio(), p.to_string(), Color::Yellow),
+                TaskStatus::Completed => (1.0, "done".to_string(), Color::Green),
+                TaskStatus::Failed(_) => (1.0, "failed".to_string(), Color::Red),
+                TaskStatus::Cancelled => (1.0, "cancelled".to_string(), Color::Magenta),
+            };
+            let marker = if task.id == self.selected_task_id() {
+                "> "
+            } else {
+                "  "
+            };
+            Gauge::default()
+                .gauge_style(Style::new().fg(color).bg(Color::Black))
+                .ratio(ratio)
+                .label(format!("{}#{} {} [{}]", marker, task.id, task.label, state))
+                .render(*row, buf);
         }
     }
 
@@ -244,30 +995,119 @@ impl Processor {
             &self.left_proc
         }
     }
+    /// The input line, if the process of that side takes one, and the list below it
+    fn panel_areas(&self, right: bool, area: Rect) -> (Option<Rect>, Rect) {
+        if self.side(right).0.input_box().is_none() {
+            return (None, area);
+        }
+        let [input_area, list_area] = Layout::vertical(constraints![==3, >=0]).areas(area);
+        (Some(input_area), list_area)
+    }
+
+    /// Where the cursor of the input box being edited is, on its own line in the focused panel
+    fn cursor_position(&self, area: Rect) -> Option<Position> {
+        if !self.is_editing {
+            return None;
+        }
+        let input = self.curr_proc().input_box()?;
+        let input_area = self.panel_areas(self.focus_right, area).0?;
+        // 边框各占 1 列
+        let width = input_area.width.saturating_sub(2);
+        let column = input.cursor_width() as u16 - input.scroll_for(width);
+        Some(Position::new(input_area.x + 1 + column, input_area.y + 1))
+    }
+
+    /// The left or right list with its process in the title
     fn render_list_panel(
         &self,
-        list: &ScrollList,
-        proc: &InProcess,
-        is_focused: bool,
-        side_name: &str,
+        right: bool,
         area: ratatui::prelude::Rect,
         buf: &mut ratatui::prelude::Buffer,
     ) {
-        let title = if is_focused {
-            if self.is_editing {
-                if self.curr_proc().len() == 0 {
-                    format!("{} - Processing", side_name)
-                } else {
-                    format!("{} - {} ", side_name, proc)
-                }
-            } else {
-                format!("{} -*{}", side_name, proc)
+        let (proc, list, policy) = self.side(right);
+        let side_name = if right { "Right" } else { "Left" };
+        let is_focused = self.focus_right == right;
+        let mut title = if is_focused && !self.is_editing {
+            format!("{} -*{}", side_name, proc.name())
+        } else {
+            format!("{} - {}", side_name, proc.name())
+        };
+        if proc.takes_policy() {
+            title += &format!(" [on conflict: {}]", policy);
+        }
+        let style = if is_focused {
+            Style::default().yellow().bold()
+        } else {
+            Style::default().dim()
+        };
+        let (input_area, list_area) = self.panel_areas(right, area);
+        if let (Some(input_area), Some(input)) = (input_area, proc.input_box()) {
+            let editing = is_focused && self.is_editing;
+            let block = Block::bordered()
+                .title(title.clone())
+                .title_bottom(Line::from(proc.input_hint()).dim())
+                .border_style(style);
+            input
+                .as_paragraph(editing, input_area.width.saturating_sub(2))
+                .block(block)
+                .render(input_area, buf);
+        }
+        if let InProcess::Rename(rule) = proc {
+            let (lines, error) = Self::rename_preview_lines(rule.input(), list);
+            let mut block = Block::bordered().title(title).border_style(style);
+            if let Some(e) = error {
+                block = block.title_bottom(Line::from(e).red());
             }
+            Paragraph::new(lines).block(block).render(list_area, buf);
         } else {
-            format!("{} - {}", side_name, proc)
+            list.render_with_border(is_focused, None, &title)
+                .render(list_area, buf);
+        }
+        if is_focused
+            && self.is_editing
+            && let Some(completion) = proc.path_input().and_then(InputBox::completion)
+        {
+            Self::render_completion(completion, list_area, buf);
+        }
+    }
+
+    /// The dropdown of path candidates, over the list right under the input line
+    fn render_completion(completion: &Completion, area: Rect, buf: &mut Buffer) {
+        const MAX_ROWS: usize = 8;
+        let rows = completion.candidates.len().min(MAX_ROWS);
+        let dropdown = Rect {
+            x: area.x + 1,
+            y: area.y,
+            width: area.width.saturating_sub(2),
+            height: (rows as u16 + 2).min(area.height),
         };
-        list.render_with_border(is_focused, None, &title)
-            .render(area, buf);
+        Clear.render(dropdown, buf);
+        // 保证选中项可见
+        let skip = completion
+            .selected
+            .map_or(0, |i| (i + 1).saturating_sub(rows));
+        let lines: Vec<Line> = completion
+            .candidates
+            .iter()
+            .enumerate()
+            .skip(skip)
+            .take(rows)
+            .map(|(i, candidate)| {
+                if completion.selected == Some(i) {
+                    Line::from(candidate.as_str()).reversed()
+                } else {
+                    Line::from(candidate.as_str())
+                }
+            })
+            .collect();
+        let title = format!(" {} candidates ", completion.candidates.len());
+        Paragraph::new(lines)
+            .block(
+                Block::bordered()
+                    .title(title)
+                    .border_style(Style::new().cyan()),
+            )
+            .render(dropdown, buf);
     }
 }
 
@@ -293,8 +1133,46 @@ impl Model for Processor {
             "[O]".bold().blue(),
             "rganize ".into(),
             "[C]".bold().blue(),
-            "opy".into(),
+            "opy ".into(),
+            "[R]".bold().blue(),
+            "ename ".into(),
+            "[Z]".bold().blue(),
+            "ip ".into(),
+            "E".into(),
+            "[X]".bold().blue(),
+            "tract ".into(),
+            "[P]".bold().blue(),
+            "olicy".into(),
         ]);
+        let completing = self.is_editing && self.curr_proc().path_input().is_some();
+        let instructions = if !self.tasks.is_empty() {
+            let finished = self.tasks.iter().filter(|t| t.is_finished()).count();
+            Line::from(vec![
+                format!(
+                    "Running... {}/{} tasks finished ",
+                    finished,
+                    self.tasks.len()
+                )
+                .into(),
+                "[↑↓]".bold().blue(),
+                " select ".into(),
+                "[C]".bold().blue(),
+                "ancel".into(),
+            ])
+        } else if completing {
+            Line::from(vec![
+                "[Tab]".bold().blue(),
+                " complete/next ".into(),
+                "[S-Tab]".bold().blue(),
+                " previous ".into(),
+                "[Enter]".bold().blue(),
+                " accept/confirm ".into(),
+                "[Esc]".bold().blue(),
+                " done".into(),
+            ])
+        } else {
+            instructions
+        };
 
         let status_style = Style::default().bg(ratatui::style::Color::DarkGray);
         Block::default()
@@ -306,52 +1184,36 @@ impl Model for Processor {
         let columns = Layout::horizontal(constraints![==50%, ==50%]);
         let [left_area, right_area] = columns.areas(main_area);
 
-        self.render_list_panel(
-            &self.left,
-            &self.left_proc,
-            !self.focus_right,
-            "Left",
-            left_area,
-            buf,
-        );
-        self.render_list_panel(
-            &self.right,
-            &self.right_proc,
-            self.focus_right,
-            "Right",
-            right_area,
-            buf,
-        );
+        self.render_list_panel(false, left_area, buf);
+        self.render_list_panel(true, right_area, buf);
 
-        if self.is_editing {
-            // right list
-            if self.focus_right {
-                frame.set_cursor_position(Position::new(
-                    right_area.x + "right - ".len() as u16 + self.right_proc.len() as u16 + 1,
-                    0,
-                ));
-            }
-            // left list
-            else {
-                frame.set_cursor_position(Position::new(
-                    left_area.x + "left - ".len() as u16 + self.left_proc.len() as u16 + 1,
-                    0,
-                ));
-            }
+        let focused_area = if self.focus_right {
+            right_area
+        } else {
+            left_area
+        };
+        if let Some(position) = self.cursor_position(focused_area) {
+            frame.set_cursor_position(position);
         }
 
         if self.double_check {
-            let horizontal = Layout::horizontal(constraints![==33%, ==34%, ==33%]).split(area);
+            let horizontal = Layout::horizontal(constraints![==10%, ==80%, ==10%]).split(area);
             let center_area =
-                Layout::vertical(constraints![==33%, ==34%, ==33%]).split(horizontal[1]);
+                Layout::vertical(constraints![==10%, ==80%, ==10%]).split(horizontal[1]);
             let popup_area = center_area[1];
             frame.render_widget(Clear, popup_area);
 
+            // 边框 2 行，标题和提示 5 行
+            let max_lines = (popup_area.height as usize).saturating_sub(7);
             let popup = component::popup::Popup::new(
                 "Warn".into(),
                 format!(
-                    "Are you sure you want to:\n1.{}\n2.{}\n[enter] to continue.\n[esc] to cancel.",
-                    self.left_proc, self.right_proc
+                    "Are you sure you want to:\n1.{}\n2.{}\n{}[enter] to continue.\n[esc] to cancel.",
+                    self.proc_summary(false),
+                    self.proc_summary(true),
+                    self.dry_run_lines(max_lines)
+                        .iter()
+                        .fold(String::new(), |acc, line| acc + "  " + line + "\n"),
                 )
                 .into(),
                 Style::new().red(),
@@ -361,17 +1223,575 @@ impl Model for Processor {
             frame.render_widget(popup, popup_area);
         }
 
+        if let Some(right) = self.asking {
+            self.render_ask_popup(right, area, frame.buffer_mut());
+        }
+
+        if !self.tasks.is_empty() {
+            self.render_progress_panel(main_area, frame.buffer_mut());
+        }
+
         Ok(())
     }
 
     fn update(&mut self, msg: &Self::Msg, _: &Self::Context) -> Self::Cmd {
         match msg {
             Msg::Exit => Cmd::Exit,
-            Msg::Key(key_event) => self
-                .handle_key_event(key_event)
-                .unwrap_or_else(|e| Cmd::Error(e.to_string())),
+            Msg::Key(key_event) => {
+                let was_open = self.double_check;
+                let cmd = self
+                    .handle_key_event(key_event)
+                    .unwrap_or_else(|e| Cmd::Error(e.to_string()));
+                // 确认框开着时两边仍可修改，每次按键后重算一次
+                if was_open && self.double_check {
+                    self.report = self.dry_run_report();
+                }
+                cmd
+            }
+            Msg::Paste(text) => {
+                if self.is_editing
+                    && self.tasks.is_empty()
+                    && self.asking.is_none()
+                    && let Some(input) = self.curr_proc_mut().input_box_mut()
+                {
+                    input.insert_str(text);
+                }
+                Cmd::None
+            }
+            Msg::TaskState(state) => {
+                let Some(task) = self.tasks.iter_mut().find(|t| t.id == state.id) else {
+                    return Cmd::None;
+                };
+                task.status = state.status.clone();
+                if let TaskStatus::Failed(e) = &task.status {
+                    tracing::error!("Task {} failed: {}", state.id, e);
+                }
+
+                self.advance()
+            }
 
             _ => Cmd::None,
         }
     }
 }
+
+/// `cmd` without the task `id`
+fn without_task(cmd: Cmd, id: u64) -> Cmd {
+    match cmd {
+        Cmd::Seq(cmds) => Cmd::Seq(cmds.into_iter().map(|c| without_task(c, id)).collect()),
+        Cmd::Batch(cmds) => Cmd::Batch(cmds.into_iter().map(|c| without_task(c, id)).collect()),
+        Cmd::InBatch(batch, cmd) => Cmd::InBatch(batch, Box::new(without_task(*cmd, id))),
+        cmd if cmd.task_ids() == [id] => Cmd::None,
+        cmd => cmd,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::model::component::FileItem;
+    use crate::core::msg::Progress;
+    use std::time::Duration;
+
+    fn list_of(dir: &std::path::Path, names: &[&str]) -> ScrollList {
+        ScrollList::new(
+            names
+                .iter()
+                .enumerate()
+                .map(|(id, name)| FileItem {
+                    id: id as u64,
+                    path: dir.join(name),
+                    display_name: name.to_string(),
+                    is_dir: false,
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_rename_preview_status() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("taken.txt"), "").unwrap();
+        let list = list_of(dir.path(), &["a1.txt", "a2.txt", "b.txt", "c.txt", "z.md"]);
+
+        let preview = Processor::rename_preview(
+            r#"RegexReplace "^a\d" "a" |> Replace "c" "b" |> Replace "z.md" "taken.txt""#,
+            &list,
+        )
+        .unwrap();
+        let statuses: Vec<_> = preview.iter().map(|(_, s)| *s).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                RenameStatus::Collision,
+                RenameStatus::Collision,
+                RenameStatus::Unchanged,
+                RenameStatus::Collision,
+                RenameStatus::Collision,
+            ]
+        );
+
+        let preview = Processor::rename_preview(r#"RegexReplace ".*" """#, &list).unwrap();
+        assert!(preview.iter().all(|(_, s)| *s == RenameStatus::Invalid));
+
+        let preview = Processor::rename_preview(r#"AddPrefix "new-""#, &list).unwrap();
+        assert_eq!(
+            preview[0],
+            ("new-a1.txt".to_string(), RenameStatus::Renamed)
+        );
+
+        // 不同目录下的同名结果不冲突
+        let list = list_of(dir.path(), &["x/a1.txt", "y/a2.txt", "y/a3.txt"]);
+        let preview = Processor::rename_preview(r#"RegexReplace "^a\d" "a""#, &list).unwrap();
+        let statuses: Vec<_> = preview.iter().map(|(_, s)| *s).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                RenameStatus::Renamed,
+                RenameStatus::Collision,
+                RenameStatus::Collision,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rename_targets_refuses_conflicts() {
+        let dir = tempfile::tempdir().unwrap();
+        let list = list_of(dir.path(), &["a.txt", "b.txt"]);
+
+        assert!(Processor::rename_targets(r#"Replace "b" "a""#, &list).is_err());
+        assert!(Processor::rename_targets("Replace", &list).is_err());
+        assert_eq!(
+            Processor::rename_targets(r#"Replace "b" "c""#, &list),
+            Ok(vec![(dir.path().join("b.txt"), dir.path().join("c.txt"))])
+        );
+    }
+
+    #[test]
+    fn test_to_plan() -> Res<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().canonicalize().unwrap();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            std::fs::write(base.join(name), name).unwrap();
+        }
+        let mut processor = Processor {
+            base: base.clone(),
+            pending: list_of(&base, &["c.txt"]),
+            left: list_of(&base, &["a.txt"]),
+            left_proc: InProcess::Move(InputBox::from("dst")),
+            left_policy: ConflictPolicy::Rename,
+            right: list_of(&base, &["b.txt"]),
+            right_proc: InProcess::Trash,
+            ..Default::default()
+        };
+        let ctrl_s = KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL);
+        let Cmd::SavePlan(plan) = processor.handle_key_event(&ctrl_s)? else {
+            panic!("expected a plan");
+        };
+        assert_eq!(plan.root, base);
+        assert_eq!(plan.pending, [PathBuf::from("c.txt")]);
+        assert_eq!(
+            plan.left,
+            PlanSide {
+                action: PlanAction::Move { to: "dst".into() },
+                on_conflict: Some(ConflictPolicy::Rename),
+                items: vec!["a.txt".into()],
+            }
+        );
+        assert_eq!(plan.right.action, PlanAction::Trash);
+        assert_eq!(plan.right.on_conflict, None);
+
+        // applying it does what confirming would
+        let cmds = plan.to_cmds(ConflictPolicy::Skip)?;
+        assert!(matches!(
+            &cmds[0],
+            Cmd::AsyncMove(_, items, to, ConflictPolicy::Rename)
+                if items == &[base.join("a.txt")] && to == &base.join("dst")
+        ));
+        assert!(matches!(&cmds[1], Cmd::AsyncTrash(_, items) if items == &[base.join("b.txt")]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_dry_run_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut processor = Processor {
+            left: list_of(dir.path(), &["a.txt", "b.txt", "c.txt"]),
+            left_proc: InProcess::Trash,
+            right: list_of(dir.path(), &["d.txt"]),
+            right_proc: InProcess::Move(InputBox::from("dst")),
+            ..Default::default()
+        };
+
+        // computed once the confirmation opens, not while drawing
+        assert!(processor.dry_run_lines(usize::MAX).is_empty());
+        assert!(matches!(processor.confirm(), Ok(Cmd::None)));
+        assert!(processor.double_check);
+        let lines = processor.dry_run_lines(usize::MAX);
+        // none of the items exist
+        assert!(lines[..4].iter().all(|l| l.starts_with("conflict: ")));
+        assert_eq!(
+            lines[4],
+            format!("trash {}", dir.path().join("a.txt").display())
+        );
+        assert_eq!(lines[7], "mkdir dst");
+        assert_eq!(lines.len(), 9);
+
+        let lines = processor.dry_run_lines(3);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[2], "... and 7 more");
+    }
+
+    #[test]
+    fn test_extract_skips_non_archives() {
+        let dir = tempfile::tempdir().unwrap();
+        let list = list_of(dir.path(), &["a.zip", "b.txt", "c.tgz"]);
+        let mut next_id = 0;
+        let cmd = Processor::proc_into_cmd(
+            &InProcess::Extract(InputBox::new()),
+            &list,
+            ConflictPolicy::Ask,
+            dir.path(),
+            &mut next_id,
+        );
+        let Cmd::Batch(cmds) = cmd else {
+            panic!("expected a batch, got {:?}", cmd);
+        };
+        let extracted: Vec<_> = cmds[..2]
+            .iter()
+            .filter_map(|cmd| match cmd {
+                Cmd::AsyncExtract(id, _, dest) => Some((*id, dest.clone())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            extracted,
+            [(0, dir.path().join("a")), (1, dir.path().join("c"))]
+        );
+        assert!(matches!(&cmds[2], Cmd::Error(e) if e.ends_with("skipped: b.txt")));
+        assert_eq!(next_id, 2);
+
+        let processor = Processor {
+            left: list,
+            left_proc: InProcess::Extract(InputBox::new()),
+            ..Default::default()
+        };
+        assert_eq!(
+            processor.proc_summary(false),
+            "Extract (1 not archives, skipped)"
+        );
+    }
+
+    #[test]
+    fn test_confirm_waits_for_every_task() {
+        use crate::core::{config::Config, context::Context, msg::TaskState};
+        use crossterm::event::KeyModifiers;
+
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = Context {
+            config: Config::default(),
+        };
+        let mut processor = Processor {
+            left: list_of(dir.path(), &["a.txt", "b.txt"]),
+            left_proc: InProcess::Trash,
+            right: list_of(dir.path(), &["c.zip", "d.zip"]),
+            right_proc: InProcess::Extract(InputBox::new()),
+            ..Default::default()
+        };
+        let enter = Msg::Key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+
+        assert!(matches!(processor.update(&enter, &ctx), Cmd::None));
+        // the right side waits for the left one
+        let Cmd::InBatch(batch, cmd) = processor.update(&enter, &ctx) else {
+            panic!("expected the left side in a journal batch");
+        };
+        assert!(matches!(*cmd, Cmd::AsyncTrash(0, _)));
+        assert_eq!(processor.tasks.len(), 3);
+        assert_eq!(processor.tasks[0].label, "Trash 2 items");
+        assert!(processor.tasks[2].label.starts_with("Extract "));
+
+        // only the progress panel takes keys while running
+        assert!(matches!(processor.update(&enter, &ctx), Cmd::None));
+        let key = |ch| Msg::Key(KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE));
+        assert!(matches!(
+            processor.update(&key('c'), &ctx),
+            Cmd::CancelTask(0)
+        ));
+        // a queued task is dropped before it is submitted
+        processor.update(&key('j'), &ctx);
+        assert!(matches!(processor.update(&key('c'), &ctx), Cmd::None));
+        assert_eq!(processor.tasks[1].status, TaskStatus::Cancelled);
+
+        let state = |id, status| Msg::TaskState(TaskState::new(id, 0, status));
+        let cmd = processor.update(&state(0, TaskStatus::Cancelled), &ctx);
+        assert_eq!(cmd.task_ids(), [2]);
+        // one confirmation is undone at once
+        assert!(matches!(cmd, Cmd::InBatch(b, _) if b == batch));
+        assert!(matches!(
+            processor.update(
+                &state(
+                    2,
+                    TaskStatus::Processing(Progress::new(1, 2, Duration::from_secs(1)))
+                ),
+                &ctx
+            ),
+            Cmd::None
+        ));
+        // a finished task can not be cancelled
+        assert!(matches!(processor.update(&key('c'), &ctx), Cmd::None));
+        assert!(matches!(
+            processor.update(&state(2, TaskStatus::Failed("x".into())), &ctx),
+            Cmd::Exit
+        ));
+
+        // without anything left to run, the queued side only ends the processor
+        processor.queued = Some(Cmd::Error("skipped".into()));
+        processor.tasks.truncate(1);
+        assert!(matches!(
+            processor.advance(),
+            Cmd::Seq(cmds) if matches!(cmds[..], [Cmd::Error(_), Cmd::Exit])
+        ));
+    }
+
+    #[test]
+    fn test_ask_policy_on_existing_target() {
+        use crate::core::{config::Config, context::Context};
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "new").unwrap();
+        std::fs::create_dir(dir.path().join("dst")).unwrap();
+        std::fs::write(dir.path().join("dst").join("a.txt"), "old").unwrap();
+        let ctx = Context {
+            config: Config::default(),
+        };
+        let mut processor = Processor::new(SelectModel::default(), ConflictPolicy::Ask, None);
+        processor.left = list_of(dir.path(), &["a.txt"]);
+        processor.left_proc = InProcess::Move(InputBox::from(
+            dir.path().join("dst").to_string_lossy().as_ref(),
+        ));
+        let key = |code| Msg::Key(KeyEvent::new(code, KeyModifiers::NONE));
+
+        assert!(matches!(
+            processor.update(&key(KeyCode::Enter), &ctx),
+            Cmd::None
+        ));
+        assert_eq!(processor.asking, Some(false));
+        assert_eq!(
+            processor.asked_targets,
+            [dir.path().join("dst").join("a.txt")]
+        );
+        assert!(!processor.double_check);
+
+        // the answer goes on to the confirmation
+        processor.update(&key(KeyCode::Char('r')), &ctx);
+        assert_eq!(processor.asking, None);
+        assert_eq!(processor.left_policy, ConflictPolicy::Rename);
+        assert!(processor.double_check);
+        let cmd = processor.update(&key(KeyCode::Enter), &ctx);
+        assert!(matches!(
+            &cmd,
+            Cmd::InBatch(_, cmd) if matches!(**cmd, Cmd::AsyncMove(_, _, _, ConflictPolicy::Rename))
+        ));
+    }
+
+    #[test]
+    fn test_ask_organize_into_existing_dir() {
+        use crate::core::{config::Config, context::Context};
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "a").unwrap();
+        std::fs::create_dir(dir.path().join("dst")).unwrap();
+        let ctx = Context {
+            config: Config::default(),
+        };
+        let mut processor = Processor {
+            base: dir.path().to_path_buf(),
+            left: list_of(dir.path(), &["a.txt"]),
+            left_proc: InProcess::Organize(InputBox::from("dst")),
+            ..Default::default()
+        };
+        let key = |code| Msg::Key(KeyEvent::new(code, KeyModifiers::NONE));
+
+        processor.update(&key(KeyCode::Enter), &ctx);
+        assert_eq!(processor.asking, Some(false));
+        // skipping, overwriting or renaming the directory itself is not possible
+        for ch in ['s', 'o', 'r'] {
+            assert!(matches!(
+                processor.update(&key(KeyCode::Char(ch)), &ctx),
+                Cmd::None
+            ));
+            assert_eq!(processor.asking, Some(false));
+        }
+        processor.update(&key(KeyCode::Char('m')), &ctx);
+        assert_eq!(processor.left_policy, ConflictPolicy::Merge);
+        let cmd = processor.update(&key(KeyCode::Enter), &ctx);
+        let Cmd::InBatch(_, cmd) = cmd else {
+            panic!("expected a journal batch");
+        };
+        assert!(matches!(
+            &*cmd,
+            Cmd::AsyncOrganize(_, _, dst, ConflictPolicy::Merge) if dst.ends_with("dst")
+        ));
+    }
+
+    #[test]
+    fn test_cycle_policy() {
+        let mut processor = Processor::new(SelectModel::default(), ConflictPolicy::Skip, None);
+        processor.focus_right = true;
+        let ctrl_p = KeyEvent::new(KeyCode::Char('p'), KeyModifiers::CONTROL);
+        processor.is_editing = true;
+        processor.handle_key_event(&ctrl_p).unwrap();
+        assert_eq!(processor.right_policy, ConflictPolicy::Overwrite);
+        assert_eq!(processor.left_policy, ConflictPolicy::Skip);
+    }
+
+    #[test]
+    fn test_destination_resolved_from_base() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut processor = Processor::new(
+            SelectModel {
+                path: dir.path().to_path_buf(),
+                ..Default::default()
+            },
+            ConflictPolicy::Ask,
+            None,
+        );
+        processor.left = list_of(dir.path(), &["a.txt"]);
+        processor.left_proc = InProcess::Move(InputBox::from("photos/../sorted"));
+
+        let cmd = processor.side_cmd(false, ConflictPolicy::Ask, &mut 0);
+        assert!(matches!(&cmd, Cmd::AsyncMove(_, _, to, _) if to == &dir.path().join("sorted")));
+        assert!(
+            processor
+                .proc_summary(false)
+                .contains(&dir.path().join("sorted").display().to_string())
+        );
+    }
+
+    #[test]
+    fn test_complete_path_recent_first() {
+        use crossterm::event::KeyModifiers;
+
+        let dir = tempfile::tempdir().unwrap();
+        for sub in ["photos", "photos-old", "music"] {
+            std::fs::create_dir(dir.path().join(sub)).unwrap();
+        }
+        let mut recent = RecentDirs::load(dir.path().join("recent.json"));
+        recent.push(dir.path().join("photos-old"));
+        // 不存在的目录不提供
+        recent.push(dir.path().join("gone"));
+        let mut processor = Processor {
+            base: dir.path().to_path_buf(),
+            left_proc: InProcess::Move(InputBox::from("ph")),
+            is_editing: true,
+            recent: Some(recent),
+            ..Default::default()
+        };
+        let recent_dir = format!("{}/", dir.path().join("photos-old").display());
+        assert_eq!(
+            processor.path_candidates("ph"),
+            [recent_dir.clone(), "photos/".to_string()]
+        );
+        assert_eq!(processor.path_candidates("").len(), 3);
+
+        let tab = KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE);
+        processor.handle_key_event(&tab).unwrap();
+        assert!(!processor.focus_right);
+        processor.handle_key_event(&tab).unwrap();
+        let input = processor.left_proc.path_input().unwrap();
+        assert_eq!(input.input(), recent_dir);
+
+        // Enter closes the dropdown instead of confirming
+        let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE);
+        processor.handle_key_event(&enter).unwrap();
+        assert!(!processor.double_check);
+        assert!(
+            processor
+                .left_proc
+                .path_input()
+                .unwrap()
+                .completion()
+                .is_none()
+        );
+        processor.handle_key_event(&enter).unwrap();
+        assert!(processor.double_check);
+    }
+
+    #[test]
+    fn test_line_editing_and_paste() {
+        use crate::core::{config::Config, context::Context};
+
+        let ctx = Context {
+            config: Config::default(),
+        };
+        let mut processor = Processor::default();
+        let key = |code, modifiers| Msg::Key(KeyEvent::new(code, modifiers));
+        processor.update(&key(KeyCode::Char('m'), KeyModifiers::NONE), &ctx);
+        processor.update(&Msg::Paste("photos/2024\n".into()), &ctx);
+        processor.update(&key(KeyCode::Left, KeyModifiers::NONE), &ctx);
+        processor.update(&key(KeyCode::Char('w'), KeyModifiers::CONTROL), &ctx);
+        processor.update(&key(KeyCode::Home, KeyModifiers::NONE), &ctx);
+        processor.update(&key(KeyCode::Delete, KeyModifiers::NONE), &ctx);
+        // 编辑时左右键移动光标，而不是切换
+        assert!(!processor.focus_right);
+        let input = processor.left_proc.input_box().unwrap();
+        assert_eq!(input.input(), "hotos/4");
+        assert_eq!(input.char_index(), 0);
+
+        // 输入行在面板顶部，加上边框
+        let area = Rect::new(10, 3, 60, 20);
+        assert_eq!(
+            processor.cursor_position(area),
+            Some(Position::new(10 + 1, 3 + 1))
+        );
+        processor.update(&key(KeyCode::End, KeyModifiers::NONE), &ctx);
+        assert_eq!(
+            processor.cursor_position(area),
+            Some(Position::new(10 + 1 + 7, 3 + 1))
+        );
+
+        processor.update(&key(KeyCode::Esc, KeyModifiers::NONE), &ctx);
+        assert!(!processor.is_editing);
+        assert_eq!(processor.cursor_position(area), None);
+        processor.update(&Msg::Paste("ignored".into()), &ctx);
+        assert_eq!(processor.left_proc.input_box().unwrap().input(), "hotos/4");
+    }
+
+    #[test]
+    fn test_cursor_with_wide_chars() {
+        let mut processor = Processor {
+            focus_right: true,
+            right_proc: InProcess::Copy(InputBox::from("照片/2024")),
+            is_editing: true,
+            ..Default::default()
+        };
+        let area = Rect::new(40, 0, 40, 20);
+        // 两个中文各占两列
+        assert_eq!(
+            processor.cursor_position(area),
+            Some(Position::new(40 + 1 + 4 + 1 + 4, 1))
+        );
+
+        // 超出宽度时滚动，光标停在最后一列
+        let long = "文件".repeat(30);
+        processor.right_proc = InProcess::Copy(InputBox::from(long.as_str()));
+        assert_eq!(
+            processor.cursor_position(area),
+            Some(Position::new(40 + 1 + 37, 1))
+        );
+
+        let mut terminal =
+            ratatui::Terminal::new(ratatui::backend::TestBackend::new(80, 20)).unwrap();
+        processor.right_proc = InProcess::Move(InputBox::from("照片"));
+        terminal
+            .draw(|frame| processor.draw(frame, frame.area()).unwrap())
+            .unwrap();
+        let buffer = terminal.backend().buffer();
+        assert_eq!(buffer[(41, 1)].symbol(), "照");
+        assert_eq!(buffer[(43, 1)].symbol(), "片");
+        assert!(buffer[(40, 0)].symbol() == "┌");
+        terminal
+            .backend_mut()
+            .assert_cursor_position(Position::new(45, 1));
+    }
+}
@@ -0,0 +1,150 @@
+use crate::core::{cmd::Cmd, model::Model, msg::Msg, report::Outcome};
+use crossterm::event::KeyCode;
+use ratatui::{
+    layout::Layout,
+    macros::constraints,
+    style::{Style, Stylize as _},
+    text::Line,
+    widgets::{Block, Paragraph, Widget as _, Wrap},
+};
+
+/// 操作结束后，有失败时显示的结果页
+#[derive(Debug, Default)]
+pub struct ResultsModel {
+    outcomes: Vec<Outcome>,
+    scroll: u16,
+}
+
+impl ResultsModel {
+    pub fn new(outcomes: Vec<Outcome>) -> Self {
+        Self {
+            outcomes,
+            scroll: 0,
+        }
+    }
+
+    /// Done operations in green, the ones not done in red followed by the error chain
+    fn lines(&self) -> Vec<Line<'static>> {
+        let mut lines = Vec::new();
+        for outcome in &self.outcomes {
+            lines.extend(
+                outcome
+                    .done
+                    .iter()
+                    .map(|op| format!("✓ {}", op).green().into()),
+            );
+            lines.extend(
+                outcome
+                    .not_done
+                    .iter()
+                    .map(|op| format!("✗ {}", op).red().into()),
+            );
+            if let Some(chain) = &outcome.error {
+                lines.extend(chain.iter().enumerate().map(|(i, cause)| {
+                    let prefix = if i == 0 { "  error: " } else { "  caused by: " };
+                    Line::from(format!("{}{}", prefix, cause)).yellow()
+                }));
+            }
+        }
+        lines
+    }
+
+    fn counts(&self) -> (usize, usize) {
+        let done = self.outcomes.iter().map(|o| o.done.len()).sum();
+        let not_done = self.outcomes.iter().map(|o| o.not_done.len()).sum();
+        (done, not_done)
+    }
+}
+
+impl Model for ResultsModel {
+    type Cmd = Cmd;
+    type Msg = Msg;
+    type Context = crate::core::context::Context;
+
+    fn update(&mut self, msg: &Self::Msg, _: &Self::Context) -> Self::Cmd {
+        match msg {
+            Msg::Exit => Cmd::Exit,
+            Msg::Key(key_event) => match key_event.code {
+                KeyCode::Char('q') | KeyCode::Esc | KeyCode::Enter => Cmd::Exit,
+                KeyCode::Up | KeyCode::Char('k') => {
+                    self.scroll = self.scroll.saturating_sub(1);
+                    Cmd::None
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    self.scroll = self.scroll.saturating_add(1);
+                    Cmd::None
+                }
+                _ => Cmd::None,
+            },
+            _ => Cmd::None,
+        }
+    }
+
+    fn draw(
+        &mut self,
+        frame: &mut ratatui::Frame,
+        area: ratatui::layout::Rect,
+    ) -> color_eyre::Result<()> {
+        let buf = frame.buffer_mut();
+        let [main_area, status_area] = Layout::vertical(constraints![>=0, ==1]).areas(area);
+
+        let lines = self.lines();
+        // 不要滚出内容
+        self.scroll = self.scroll.min(lines.len().saturating_sub(1) as u16);
+
+        let (done, not_done) = self.counts();
+        let title = Line::from(vec![
+            " Results: ".bold(),
+            format!("{} done", done).green(),
+            ", ".into(),
+            format!("{} failed ", not_done).red(),
+        ]);
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .scroll((self.scroll, 0))
+            .block(
+                Block::bordered()
+                    .title(title)
+                    .border_style(Style::new().red()),
+            )
+            .render(main_area, buf);
+
+        Block::default()
+            .style(Style::default().bg(ratatui::style::Color::DarkGray))
+            .render(status_area, buf);
+        Line::from(vec![
+            "[↑↓]".bold().blue(),
+            " scroll ".into(),
+            "[q/enter]".bold().blue(),
+            " quit".into(),
+        ])
+        .render(status_area, buf);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::journal::FsOp;
+
+    #[test]
+    fn test_results_lines() {
+        let model = ResultsModel::new(vec![Outcome {
+            done: vec![FsOp::Mkdir { path: "dst".into() }],
+            not_done: vec![FsOp::Trash { path: "a".into() }],
+            error: Some(vec!["Failed to trash".into(), "permission denied".into()]),
+        }]);
+        let lines: Vec<String> = model.lines().iter().map(|l| l.to_string()).collect();
+        assert_eq!(
+            lines,
+            [
+                "✓ mkdir dst",
+                "✗ trash a",
+                "  error: Failed to trash",
+                "  caused by: permission denied",
+            ]
+        );
+        assert_eq!(model.counts(), (1, 1));
+    }
+}
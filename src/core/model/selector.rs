@@ -1,14 +1,17 @@
 use std::env::home_dir;
-use std::{collections::VecDeque, path::PathBuf};
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+};
 
-use crate::core::file_ops::{self};
+use crate::core::file_ops::{self, ViewMode};
 use crate::core::{
     cmd::Cmd,
-    model::component::{Cursor, FileItem, History, ListType, ScrollList},
+    model::component::{Cursor, FileItem, History, ListType, ScrollList, input::InputBox},
     msg::Msg,
 };
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
-use ratatui::layout::Constraint;
+use ratatui::layout::{Constraint, Position};
 use ratatui::macros::constraints;
 use ratatui::style::{self, Style};
 use ratatui::{
@@ -44,20 +47,40 @@ pub struct SelectModel {
     pub(crate) history: History<SelectOperation>,
     pub(crate) show_hidden: bool,
     pub(crate) respect_gitignore: bool,
+    /// files of the whole tree instead of the direct children
+    pub(crate) recursive: bool,
+    /// ids stay unique across directories, since Left and Right keep their items
+    pub(crate) next_id: u64,
+    /// selected once the directory is loaded, the one we came up from
+    pub(crate) reselect: Option<PathBuf>,
+    /// narrows the visible Pending items, see [`fuzzy_match`]
+    pub(crate) filter: InputBox,
+    /// typing into `filter`
+    pub(crate) filtering: bool,
 }
 
 impl SelectModel {
-    pub fn new(current_path: PathBuf, show_hidden: bool, respect_gitignore: bool) -> Res<Self> {
-        let res = file_ops::list_items(&current_path, show_hidden, respect_gitignore)?;
+    pub fn new(
+        current_path: PathBuf,
+        show_hidden: bool,
+        respect_gitignore: bool,
+        view: ViewMode,
+    ) -> Res<Self> {
+        let res = file_ops::list_items(&current_path, show_hidden, respect_gitignore, view)?;
         let mut model = Self::new_with(current_path, res);
         model.show_hidden = show_hidden;
         model.respect_gitignore = respect_gitignore;
+        model.recursive = matches!(view, ViewMode::Recursive { .. });
         model.sync_view();
         Ok(model)
     }
 
     fn new_with(path: PathBuf, pending: VecDeque<FileItem>) -> Self {
         Self {
+            next_id: pending.iter().map(|i| i.id + 1).max().unwrap_or(0),
+            reselect: None,
+            filter: InputBox::new(),
+            filtering: false,
             path,
             all_items: pending.clone(),
             mid: ScrollList::new(pending),
@@ -67,6 +90,7 @@ impl SelectModel {
             cursor: Cursor::new(ListType::Mid),
             show_hidden: false,
             respect_gitignore: true,
+            recursive: false,
         }
     }
 
@@ -78,6 +102,189 @@ impl SelectModel {
             .cloned()
             .collect();
         self.mid = ScrollList::new(filtered);
+        self.fix_selection(ListType::Mid);
+    }
+
+    /// Indices into `mid.items` matching the filter, in order, with the matched char positions
+    fn mid_matches(&self) -> Vec<(usize, Vec<usize>)> {
+        self.mid
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(i, item)| {
+                fuzzy_match(self.filter.input(), &item.display_name).map(|pos| (i, pos))
+            })
+            .collect()
+    }
+
+    /// The Mid item under the cursor, the selection counts only the matches of the filter
+    fn selected_mid_item(&self) -> Option<&FileItem> {
+        let index = self.mid_matches().get(self.mid.state.selected()?)?.0;
+        self.mid.items.get(index)
+    }
+
+    /// How many items of the list are shown, the selection indexes into them
+    fn visible_len(&self, list_type: ListType) -> usize {
+        match list_type {
+            ListType::Mid => self.mid_matches().len(),
+            _ => self.get_list(list_type).items.len(),
+        }
+    }
+
+    /// Keep the selection on a visible item, None if there is none
+    fn fix_selection(&mut self, list_type: ListType) {
+        let len = self.visible_len(list_type);
+        let state = &mut self.get_list_mut(list_type).state;
+        let selected = (len > 0).then(|| state.selected().unwrap_or(0).min(len - 1));
+        state.select(selected);
+    }
+
+    /// Select the item at `index` in `items`, if it is visible
+    fn select_item(&mut self, list_type: ListType, index: usize) {
+        let visible = match list_type {
+            ListType::Mid => self.mid_matches().iter().position(|(i, _)| *i == index),
+            _ => Some(index),
+        };
+        if visible.is_some() {
+            self.get_list_mut(list_type).state.select(visible);
+        }
+        self.fix_selection(list_type);
+    }
+
+    /// The filter changed, start over from the first match
+    fn refilter(&mut self) {
+        self.mid.state.select(None);
+        self.fix_selection(ListType::Mid);
+    }
+
+    fn clear_filter(&mut self) {
+        self.filter = InputBox::new();
+        self.filtering = false;
+        self.fix_selection(ListType::Mid);
+    }
+
+    /// Keys while typing the filter
+    fn handle_filter_key(&mut self, key_event: &KeyEvent) {
+        match key_event.code {
+            KeyCode::Esc => self.clear_filter(),
+            KeyCode::Enter => self.filtering = false,
+            KeyCode::Up => self.mid.up(),
+            KeyCode::Down => {
+                self.mid.down();
+                self.fix_selection(ListType::Mid);
+            }
+            KeyCode::Backspace if !key_event.modifiers.contains(KeyModifiers::ALT) => {
+                self.filter.delete_char();
+                self.refilter();
+            }
+            KeyCode::Char(c) if !key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.filter.enter_char(c);
+                self.refilter();
+            }
+            _ => {
+                if self.filter.handle_edit_key(key_event) {
+                    self.refilter();
+                }
+            }
+        }
+    }
+
+    /// Show freshly listed `items` as pending, leaving out the ones already in Left or Right
+    fn set_items(&mut self, items: VecDeque<FileItem>) {
+        // 重新加载同一目录时保持选中项
+        let selected = self
+            .reselect
+            .take()
+            .or_else(|| self.selected_mid_item().map(|i| i.path.clone()));
+        let assigned: std::collections::HashSet<_> = self
+            .left
+            .items
+            .iter()
+            .chain(self.right.items.iter())
+            .map(|i| i.path.clone())
+            .collect();
+        self.all_items = items
+            .into_iter()
+            .filter(|i| !assigned.contains(&i.path))
+            .map(|mut i| {
+                i.id = self.next_id;
+                self.next_id += 1;
+                i
+            })
+            .collect();
+        self.sync_view();
+        if let Some(path) = selected
+            && let Some(index) = self.mid.items.iter().position(|i| i.path == path)
+        {
+            self.select_item(ListType::Mid, index);
+        }
+    }
+
+    /// Whether `path` is listed in the current directory by the current view
+    fn is_listed_here(&self, path: &Path) -> bool {
+        if self.recursive {
+            path.starts_with(&self.path)
+        } else {
+            path.parent() == Some(self.path.as_path())
+        }
+    }
+
+    /// Show `path` instead, Left and Right keep what they have
+    fn change_dir(&mut self, path: PathBuf) -> Cmd {
+        self.path = path.clone();
+        self.all_items.clear();
+        self.sync_view();
+        self.cursor.focus = ListType::Mid;
+        Cmd::Seq(vec![Cmd::ChangeWatchPath(path.clone()), Cmd::LoadDir(path)])
+    }
+
+    /// Go into the selected pending directory
+    fn enter_dir(&mut self) -> Option<Cmd> {
+        if self.cursor.focus != ListType::Mid {
+            return None;
+        }
+        let item = self.selected_mid_item()?;
+        item.is_dir
+            .then(|| item.path.clone())
+            .map(|path| self.change_dir(path))
+    }
+
+    /// Go up to the parent directory and select the one we left
+    fn leave_dir(&mut self) -> Option<Cmd> {
+        let parent = self.path.parent()?.to_path_buf();
+        self.reselect = Some(self.path.clone());
+        Some(self.change_dir(parent))
+    }
+
+    /// `~ › Photos › 2024`, the current directory in bold
+    fn breadcrumbs(&self) -> Line<'static> {
+        let home = home_dir();
+        let (head, rest) = match home.as_ref().and_then(|h| self.path.strip_prefix(h).ok()) {
+            Some(rest) => ("~".to_string(), rest),
+            None => match self.path.strip_prefix("/") {
+                Ok(rest) => ("/".to_string(), rest),
+                Err(_) => (String::new(), self.path.as_path()),
+            },
+        };
+        let mut crumbs: Vec<String> = rest
+            .iter()
+            .map(|c| c.to_string_lossy().to_string())
+            .collect();
+        crumbs.insert(0, head);
+        crumbs.retain(|c| !c.is_empty());
+        let last = crumbs.len().saturating_sub(1);
+        let mut spans = vec![" ".into()];
+        for (i, crumb) in crumbs.into_iter().enumerate() {
+            if i > 0 {
+                spans.push(" › ".dim());
+            }
+            spans.push(if i == last {
+                crumb.bold()
+            } else {
+                crumb.into()
+            });
+        }
+        Line::from(spans)
     }
 
     fn get_list_mut(&mut self, list_type: ListType) -> &mut ScrollList {
@@ -97,8 +304,13 @@ impl SelectModel {
     }
 
     fn move_item(&mut self, from_focus: ListType, to_focus: ListType) -> Option<()> {
+        let selected = self.get_list(from_focus).state.selected()?;
+        // 过滤时选中的是可见项，记录它在整个列表里的位置
+        let from_index = match from_focus {
+            ListType::Mid => self.mid_matches().get(selected)?.0,
+            _ => selected,
+        };
         let from_list = self.get_list_mut(from_focus);
-        let from_index = from_list.state.selected()?;
         if from_list.items.is_empty() {
             return None;
         }
@@ -107,13 +319,7 @@ impl SelectModel {
         let item_id = item.id;
 
         // 移除后修正原列表的选中项（防止越界）
-        if from_index >= from_list.items.len() {
-            from_list
-                .state
-                .select(Some(from_list.items.len().saturating_sub(1)));
-        } else if from_list.items.is_empty() {
-            from_list.state.select(None);
-        }
+        self.fix_selection(from_focus);
 
         let cmd = SelectOperation::Move {
             item_id,
@@ -125,7 +331,7 @@ impl SelectModel {
         let updated_item = item;
         let to_list = self.get_list_mut(to_focus);
         to_list.items.push_front(updated_item);
-        to_list.state.select(Some(0)); // 移动过去后选中新项
+        self.select_item(to_focus, 0); // 移动过去后选中新项
 
         self.history.log(cmd);
 
@@ -151,12 +357,20 @@ impl SelectModel {
                     // 2. 恢复其原始路径，因为在执行 Move 命令时，item 的 path 已经被更新为 new_path
 
                     // 3. 放回“来源列表”的原始位置
+                    // 别的目录的项目不放回 Pending，回到那个目录时会再列出来
+                    if from_list == ListType::Mid && !self.is_listed_here(&item.path) {
+                        self.fix_selection(to_list);
+                        self.history.undo();
+                        return Some(());
+                    }
                     let source_list = &mut self.get_list_mut(from_list).items;
                     if from_index >= source_list.len() {
                         source_list.push_back(item);
                     } else {
                         source_list.insert(from_index, item);
                     }
+                    self.fix_selection(to_list);
+                    self.fix_selection(from_list);
                 }
             }
         }
@@ -165,6 +379,31 @@ impl SelectModel {
         Some(())
     }
 
+    /// Re-apply the last undone operation
+    fn redo(&mut self) -> Option<()> {
+        match self.history.next()?.clone() {
+            SelectOperation::Move {
+                item_id,
+                from_list,
+                to_list,
+                ..
+            } => {
+                let source_list = self.get_list_mut(from_list);
+                let pos = source_list.items.iter().position(|i| i.id == item_id)?;
+                let item = source_list.items.remove(pos)?;
+                self.fix_selection(from_list);
+
+                // 和 move_item 一样放到最前面
+                let target_list = self.get_list_mut(to_list);
+                target_list.items.push_front(item);
+                self.select_item(to_list, 0);
+            }
+        }
+
+        self.history.redo();
+        Some(())
+    }
+
     fn as_lines(scroll_list: &ScrollList, is_focus: bool) -> Vec<Line<'static>> {
         scroll_list
             .items
@@ -194,7 +433,43 @@ impl SelectModel {
         list.block(block)
     }
 
+    /// The visible Pending items with their matched chars highlighted, the filter at the bottom
+    fn mid_list(&self) -> List<'static> {
+        let is_focus = self.cursor.focus == ListType::Mid;
+        let matches = self.mid_matches();
+        let lines: Vec<Line> = matches
+            .iter()
+            .enumerate()
+            .map(|(visible, (i, positions))| {
+                let mut line = highlighted_line(&self.mid.items[*i], positions);
+                if is_focus && self.mid.state.selected() == Some(visible) {
+                    line = line.reversed();
+                }
+                line
+            })
+            .collect();
+        let list_style = if is_focus {
+            Style::default().fg(style::Color::Yellow).bold()
+        } else {
+            style::Style::default()
+        };
+        let mut block = Block::bordered().border_style(list_style);
+        if self.filtering || !self.filter.input().is_empty() {
+            let title = format!("Pending {}/{}", matches.len(), self.mid.items.len());
+            block = block
+                .title(Line::from(title).centered())
+                .title_bottom(Line::from(format!("/{}", self.filter.input())));
+        } else {
+            block = block.title(Line::from("Pending").centered());
+        }
+        List::new(lines).block(block)
+    }
+
     fn handle_key_event(&mut self, key_event: &KeyEvent) -> Res<Cmd> {
+        if self.filtering {
+            self.handle_filter_key(key_event);
+            return Ok(Cmd::None);
+        }
         match key_event.code {
             KeyCode::Left => {
                 self.move_item(self.cursor.focus, self.cursor.focus.left());
@@ -207,11 +482,31 @@ impl SelectModel {
             }
             KeyCode::Down => {
                 self.get_list_mut(self.cursor.focus).down();
+                self.fix_selection(self.cursor.focus);
+            }
+            KeyCode::Char('/') => {
+                self.filtering = true;
+                self.cursor.focus = ListType::Mid;
             }
+            KeyCode::Esc if !self.filter.input().is_empty() => self.clear_filter(),
             KeyCode::Char('q') | KeyCode::Esc => return Ok(Cmd::Exit),
+            // Ctrl+Shift+Z 在不同终端下可能是 'Z' 或者 'z' + SHIFT
+            KeyCode::Char(c @ ('z' | 'Z'))
+                if key_event.modifiers.contains(KeyModifiers::CONTROL)
+                    && (c == 'Z' || key_event.modifiers.contains(KeyModifiers::SHIFT)) =>
+            {
+                _ = self.redo();
+            }
+            KeyCode::Char('y') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                _ = self.redo();
+            }
             KeyCode::Char('z') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
                 _ = self.undo();
             }
+            // 撤销上一次真正执行过的文件操作
+            KeyCode::Char('U') => {
+                return Ok(Cmd::Seq(vec![Cmd::Undo, Cmd::LoadDir(self.path.clone())]));
+            }
             KeyCode::Enter => return Ok(Cmd::IntoProcess(self.clone())),
             KeyCode::Tab => {}
             KeyCode::Char('.') => {
@@ -226,9 +521,24 @@ impl SelectModel {
                     Cmd::LoadDir(self.path.clone()),
                 ]));
             }
-            KeyCode::Char(':') => {
-                
+            KeyCode::Char('v') => {
+                self.recursive = !self.recursive;
+                return Ok(Cmd::Seq(vec![
+                    Cmd::ToggleRecursive,
+                    Cmd::LoadDir(self.path.clone()),
+                ]));
+            }
+            KeyCode::Char('l') => {
+                if let Some(cmd) = self.enter_dir() {
+                    return Ok(cmd);
+                }
+            }
+            KeyCode::Char('h') | KeyCode::Backspace => {
+                if let Some(cmd) = self.leave_dir() {
+                    return Ok(cmd);
+                }
             }
+            KeyCode::Char(':') => {}
             _ => {}
         }
         Ok(Cmd::None)
@@ -249,19 +559,7 @@ impl crate::core::model::Model for SelectModel {
         let [main_area, status_area] =
             Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(area);
 
-        let path_display = if let Some(home) = home_dir() {
-            if self.path == home {
-                "~".to_string()
-            } else if let Ok(stripped) = self.path.strip_prefix(&home) {
-                format!("~/{}", stripped.display())
-            } else {
-                self.path.to_string_lossy().to_string()
-            }
-        } else {
-            self.path.to_string_lossy().to_string()
-        };
-
-        let status_left = Line::from(vec![" ".into(), path_display.into()]);
+        let status_left = self.breadcrumbs();
 
         let status_right = Line::from(vec![
             format!(" {} items ", self.mid.items.len()).into(),
@@ -278,6 +576,12 @@ impl crate::core::model::Model for SelectModel {
             } else {
                 "OFF".red().bold()
             },
+            " View: ".into(),
+            if self.recursive {
+                "TREE".green().bold()
+            } else {
+                "FLAT".into()
+            },
             " ".into(),
         ]);
 
@@ -309,12 +613,7 @@ impl crate::core::model::Model for SelectModel {
             &mut self.left.state,
         );
         // mid - pending
-        StatefulWidget::render(
-            Self::as_list(&self.mid, self.cursor.focus == ListType::Mid, "Pending"),
-            mid_area,
-            buf,
-            &mut self.mid.state,
-        );
+        StatefulWidget::render(self.mid_list(), mid_area, buf, &mut self.mid.state);
         // right
         StatefulWidget::render(
             Self::as_list(&self.right, self.cursor.focus == ListType::Right, "Right"),
@@ -323,6 +622,15 @@ impl crate::core::model::Model for SelectModel {
             &mut self.right.state,
         );
 
+        if self.filtering {
+            // 底边上 "/" 之后
+            let x = mid_area.x + 2 + self.filter.cursor_width() as u16;
+            frame.set_cursor_position(Position::new(
+                x.min(mid_area.right().saturating_sub(2)),
+                mid_area.bottom().saturating_sub(1),
+            ));
+        }
+
         Ok(())
     }
     fn update(&mut self, msg: &Self::Msg, _ctx: &Self::Context) -> Self::Cmd {
@@ -332,19 +640,17 @@ impl crate::core::model::Model for SelectModel {
                 .handle_key_event(ket_event)
                 .unwrap_or_else(|e| Cmd::Error(e.to_string())),
             Msg::DirLoaded(path, items) => {
+                // 切换目录后，之前目录的结果作废
                 if *path == self.path {
-                    // 去重逻辑：剔除已经在 Left 或 Right 列表中的文件
-                    let mut new_items = items.clone();
-                    let pending_names: std::collections::HashSet<_> = self
-                        .left
-                        .items
-                        .iter()
-                        .chain(self.right.items.iter())
-                        .map(|i| i.display_name.clone())
-                        .collect();
-                    new_items.retain(|i| !pending_names.contains(&i.display_name));
-                    self.all_items = new_items;
-                    self.sync_view();
+                    self.set_items(items.clone());
+                }
+                Cmd::None
+            }
+            Msg::FileChanged => Cmd::LoadDir(self.path.clone()),
+            Msg::Paste(text) => {
+                if self.filtering {
+                    self.filter.insert_str(text);
+                    self.refilter();
                 }
                 Cmd::None
             }
@@ -352,3 +658,346 @@ impl crate::core::model::Model for SelectModel {
         }
     }
 }
+
+/// Char positions of `text` matching the chars of `pattern` in order, spaces ignored.
+/// Case-insensitive unless `pattern` has an uppercase char.
+fn fuzzy_match(pattern: &str, text: &str) -> Option<Vec<usize>> {
+    let case_sensitive = pattern.chars().any(char::is_uppercase);
+    let eq = |p: char, c: char| {
+        if case_sensitive {
+            p == c
+        } else {
+            p.to_lowercase().eq(c.to_lowercase())
+        }
+    };
+    let mut pattern = pattern.chars().filter(|c| !c.is_whitespace()).peekable();
+    let mut positions = Vec::new();
+    for (i, c) in text.chars().enumerate() {
+        let Some(&p) = pattern.peek() else {
+            break;
+        };
+        if eq(p, c) {
+            positions.push(i);
+            pattern.next();
+        }
+    }
+    pattern.peek().is_none().then_some(positions)
+}
+
+/// Like [`FileItem::as_line`], with the chars at `positions` highlighted
+fn highlighted_line(item: &FileItem, positions: &[usize]) -> Line<'static> {
+    let base = if item.is_dir {
+        Style::default().blue()
+    } else {
+        Style::default()
+    };
+    let mut spans: Vec<ratatui::text::Span> = item
+        .display_name
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let style = if positions.contains(&i) {
+                base.yellow().bold().underlined()
+            } else {
+                base
+            };
+            ratatui::text::Span::styled(c.to_string(), style)
+        })
+        .collect();
+    if item.is_dir {
+        spans.push(ratatui::text::Span::styled("/", base));
+    }
+    Line::from(spans)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn model_with(n: u64) -> SelectModel {
+        let items = (0..n)
+            .map(|id| FileItem {
+                id,
+                path: PathBuf::from(format!("/tmp/{}", id)),
+                display_name: id.to_string(),
+                is_dir: false,
+            })
+            .collect();
+        let mut model = SelectModel::new_with(PathBuf::from("/tmp"), items);
+        model.sync_view();
+        model
+    }
+
+    fn ids(list: &ScrollList) -> Vec<u64> {
+        list.items.iter().map(|i| i.id).collect()
+    }
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn test_undo_redo_long_chain() {
+        let mut model = model_with(6);
+        let mut snapshots = vec![(ids(&model.left), ids(&model.mid), ids(&model.right))];
+
+        // mid -> left, mid -> right alternately, picking from different positions
+        for step in 0..6 {
+            model
+                .mid
+                .state
+                .select(Some(step % 3 % model.mid.items.len()));
+            let to = if step % 2 == 0 {
+                ListType::Left
+            } else {
+                ListType::Right
+            };
+            model.move_item(ListType::Mid, to).unwrap();
+            snapshots.push((ids(&model.left), ids(&model.mid), ids(&model.right)));
+        }
+        assert!(model.mid.items.is_empty());
+        assert!(model.redo().is_none());
+
+        for expected in snapshots.iter().rev().skip(1) {
+            model.undo().unwrap();
+            assert_eq!(
+                &(ids(&model.left), ids(&model.mid), ids(&model.right)),
+                expected
+            );
+        }
+        assert!(model.history.last().is_none());
+
+        for expected in snapshots.iter().skip(1) {
+            model.redo().unwrap();
+            assert_eq!(
+                &(ids(&model.left), ids(&model.mid), ids(&model.right)),
+                expected
+            );
+        }
+        assert!(model.redo().is_none());
+    }
+
+    #[test]
+    fn test_new_move_drops_redo_branch() {
+        let mut model = model_with(3);
+        model.move_item(ListType::Mid, ListType::Left).unwrap();
+        model.move_item(ListType::Mid, ListType::Left).unwrap();
+        model.undo().unwrap();
+        model.undo().unwrap();
+
+        model.move_item(ListType::Mid, ListType::Right).unwrap();
+        assert!(model.redo().is_none());
+        assert_eq!(ids(&model.right), vec![0]);
+        assert!(model.left.items.is_empty());
+
+        model.undo().unwrap();
+        assert_eq!(ids(&model.mid), vec![0, 1, 2]);
+        model.redo().unwrap();
+        assert_eq!(ids(&model.right), vec![0]);
+    }
+
+    #[test]
+    fn test_redo_key_bindings() {
+        let mut model = model_with(2);
+        model.move_item(ListType::Mid, ListType::Left).unwrap();
+
+        for redo_key in [
+            key(KeyCode::Char('y'), KeyModifiers::CONTROL),
+            key(
+                KeyCode::Char('Z'),
+                KeyModifiers::CONTROL | KeyModifiers::SHIFT,
+            ),
+            key(
+                KeyCode::Char('z'),
+                KeyModifiers::CONTROL | KeyModifiers::SHIFT,
+            ),
+            key(KeyCode::Char('Z'), KeyModifiers::CONTROL),
+        ] {
+            model
+                .handle_key_event(&key(KeyCode::Char('z'), KeyModifiers::CONTROL))
+                .unwrap();
+            assert!(model.left.items.is_empty());
+            model.handle_key_event(&redo_key).unwrap();
+            assert_eq!(ids(&model.left), vec![0]);
+        }
+    }
+
+    #[test]
+    fn test_navigate_keeps_assigned() -> Res<()> {
+        use crate::core::{config::Config, context::Context, model::Model as _};
+
+        let dir = tempfile::tempdir()?;
+        let root = dir.path().to_path_buf();
+        let sub = root.join("sub");
+        std::fs::create_dir(&sub)?;
+        std::fs::write(root.join("b.txt"), "")?;
+        std::fs::write(sub.join("a.txt"), "")?;
+        let ctx = Context {
+            config: Config::default(),
+        };
+        let mut model = SelectModel::new(root.clone(), false, true, ViewMode::Flat)?;
+        // 模拟 runner 处理 LoadDir
+        let load = |model: &mut SelectModel, cmd: Cmd| {
+            let Cmd::Seq(cmds) = cmd else {
+                panic!("expected a seq, got {:?}", cmd)
+            };
+            assert!(matches!(&cmds[0], Cmd::ChangeWatchPath(p) if *p == model.path));
+            let items = file_ops::list_items(&model.path, false, true, ViewMode::Flat).unwrap();
+            model.update(&Msg::DirLoaded(model.path.clone(), items), &ctx);
+        };
+
+        // 只能进入目录
+        model.mid.state.select(Some(0));
+        assert_eq!(model.mid.items[0].display_name, "b.txt");
+        assert!(model.enter_dir().is_none());
+        model.mid.state.select(Some(1));
+        let cmd = model.handle_key_event(&key(KeyCode::Char('l'), KeyModifiers::NONE))?;
+        load(&mut model, cmd);
+        assert_eq!(model.path, sub);
+        model.move_item(ListType::Mid, ListType::Left).unwrap();
+
+        let cmd = model.handle_key_event(&key(KeyCode::Backspace, KeyModifiers::NONE))?;
+        load(&mut model, cmd);
+        assert_eq!(model.path, root);
+        // 回到父目录时选中刚离开的目录
+        assert_eq!(model.mid.state.selected(), Some(1));
+        model.mid.state.select(Some(0));
+        model.move_item(ListType::Mid, ListType::Right).unwrap();
+        assert_eq!(model.left.items[0].path, sub.join("a.txt"));
+        assert_eq!(model.right.items[0].path, root.join("b.txt"));
+        assert_ne!(model.left.items[0].id, model.right.items[0].id);
+
+        // 别的目录的项目撤销后不出现在这里
+        model.undo().unwrap();
+        model.undo().unwrap();
+        assert!(model.left.items.is_empty());
+        assert_eq!(model.mid.items.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_changed_keeps_selection() {
+        use crate::core::{config::Config, context::Context, model::Model as _};
+
+        let ctx = Context {
+            config: Config::default(),
+        };
+        let mut model = model_with(3);
+        model.mid.state.select(Some(2));
+        assert!(matches!(
+            model.update(&Msg::FileChanged, &ctx),
+            Cmd::LoadDir(p) if p == model.path
+        ));
+        let mut items = model.all_items.clone();
+        items.pop_front();
+        model.update(&Msg::DirLoaded(model.path.clone(), items), &ctx);
+        assert_eq!(model.mid.items.len(), 2);
+        assert_eq!(model.mid.state.selected(), Some(1));
+    }
+
+    #[test]
+    fn test_filtered_enter_and_reload() {
+        use crate::core::{config::Config, context::Context, model::Model as _};
+
+        let ctx = Context {
+            config: Config::default(),
+        };
+        let items = [
+            ("a.txt", false),
+            ("b", false),
+            ("photos", true),
+            ("pics", true),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(id, (name, is_dir))| FileItem {
+            id: id as u64,
+            path: PathBuf::from("/tmp").join(name),
+            display_name: name.to_string(),
+            is_dir,
+        })
+        .collect();
+        let mut model = SelectModel::new_with(PathBuf::from("/tmp"), items);
+        model.sync_view();
+        let press = |model: &mut SelectModel, code| {
+            model
+                .handle_key_event(&key(code, KeyModifiers::NONE))
+                .unwrap()
+        };
+        press(&mut model, KeyCode::Char('/'));
+        press(&mut model, KeyCode::Char('p'));
+        press(&mut model, KeyCode::Down);
+        press(&mut model, KeyCode::Enter);
+        // 选中的是第二个匹配 pics，而不是 mid 里的第二项 b
+        assert_eq!(model.selected_mid_item().unwrap().display_name, "pics");
+
+        // 重新加载后仍选中 pics
+        let items = model.all_items.clone();
+        model.update(&Msg::DirLoaded(model.path.clone(), items), &ctx);
+        assert_eq!(model.mid.state.selected(), Some(1));
+        assert_eq!(model.selected_mid_item().unwrap().display_name, "pics");
+
+        let Cmd::Seq(cmds) = press(&mut model, KeyCode::Char('l')) else {
+            panic!("expected to enter pics");
+        };
+        assert!(matches!(&cmds[0], Cmd::ChangeWatchPath(p) if p.ends_with("pics")));
+    }
+
+    #[test]
+    fn test_fuzzy_match() {
+        assert_eq!(fuzzy_match("", "abc"), Some(vec![]));
+        assert_eq!(fuzzy_match("ac", "abc"), Some(vec![0, 2]));
+        assert_eq!(fuzzy_match("ca", "abc"), None);
+        assert_eq!(
+            fuzzy_match("img jpg", "IMG_001.jpg"),
+            Some(vec![0, 1, 2, 8, 9, 10])
+        );
+        // 有大写时区分大小写
+        assert_eq!(fuzzy_match("Img", "img.png"), None);
+        assert_eq!(fuzzy_match("照片", "旅行照片.png"), Some(vec![2, 3]));
+    }
+
+    #[test]
+    fn test_filtered_move_and_undo() {
+        let mut model = model_with(12);
+        let press = |model: &mut SelectModel, code| {
+            model
+                .handle_key_event(&key(code, KeyModifiers::NONE))
+                .unwrap()
+        };
+        press(&mut model, KeyCode::Char('/'));
+        press(&mut model, KeyCode::Char('1'));
+        // 1, 10, 11
+        assert_eq!(model.visible_len(ListType::Mid), 3);
+        // 过滤时 q 是输入
+        press(&mut model, KeyCode::Char('q'));
+        assert_eq!(model.visible_len(ListType::Mid), 0);
+        assert_eq!(model.mid.state.selected(), None);
+        press(&mut model, KeyCode::Backspace);
+        press(&mut model, KeyCode::Down);
+        press(&mut model, KeyCode::Enter);
+        assert!(!model.filtering);
+        assert_eq!(model.filter.input(), "1");
+
+        press(&mut model, KeyCode::Left);
+        assert_eq!(ids(&model.left), vec![10]);
+        let Some(SelectOperation::Move { from_index, .. }) = model.history.last() else {
+            panic!("no move recorded");
+        };
+        assert_eq!(*from_index, 10);
+        // 选中项留在下一个匹配上
+        assert_eq!(model.visible_len(ListType::Mid), 2);
+        assert_eq!(model.mid.state.selected(), Some(1));
+        model.move_item(ListType::Mid, ListType::Left).unwrap();
+        assert_eq!(ids(&model.left), vec![11, 10]);
+        assert_eq!(model.mid.state.selected(), Some(0));
+
+        // Esc 先清掉过滤
+        assert!(matches!(press(&mut model, KeyCode::Esc), Cmd::None));
+        assert_eq!(model.visible_len(ListType::Mid), 10);
+        model.undo().unwrap();
+        model.undo().unwrap();
+        assert_eq!(ids(&model.mid), (0..12).collect::<Vec<_>>());
+        assert!(matches!(press(&mut model, KeyCode::Esc), Cmd::Exit));
+    }
+}
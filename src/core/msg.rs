@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
 use crate::core::model::component::FileItem;
 use crossterm::event::{KeyEvent, MouseEvent};
@@ -34,9 +34,76 @@ pub enum Msg {
 #[derive(Debug, Clone, PartialEq)]
 pub enum TaskStatus {
     Pending,
-    Processing(f32), // 进度百分比
+    Processing(Progress),
     Completed,
     Failed(String),
+    /// stopped by the user
+    Cancelled,
+}
+
+/// 任务进度，以字节计
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Progress {
+    pub done: u64,
+    pub total: u64,
+    /// bytes per second since the task started
+    pub throughput: Option<f64>,
+    pub eta: Option<Duration>,
+}
+
+impl Progress {
+    /// `elapsed` is the time since the task started
+    pub fn new(done: u64, total: u64, elapsed: Duration) -> Self {
+        let secs = elapsed.as_secs_f64();
+        let throughput = (secs > 0.0 && done > 0).then(|| done as f64 / secs);
+        let eta = throughput
+            .map(|speed| Duration::from_secs_f64(total.saturating_sub(done) as f64 / speed));
+        Self {
+            done,
+            total,
+            throughput,
+            eta,
+        }
+    }
+
+    pub fn ratio(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            (self.done as f64 / self.total as f64).clamp(0.0, 1.0)
+        }
+    }
+}
+
+/// e.g. `42% 12.5 MiB/s ETA 1:05`
+impl std::fmt::Display for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.0}%", self.ratio() * 100.0)?;
+        if let Some(speed) = self.throughput {
+            write!(f, " {}/s", human_bytes(speed as u64))?;
+        }
+        if let Some(eta) = self.eta {
+            let secs = eta.as_secs();
+            write!(f, " ETA {}:{:02}", secs / 60, secs % 60)?;
+        }
+        Ok(())
+    }
+}
+
+/// 1024 进制，保留一位小数
+pub fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -56,3 +123,28 @@ impl TaskState {
 pub enum FileEvent {
     FileChanged,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_progress() {
+        let progress = Progress::new(25 * 1024 * 1024, 100 * 1024 * 1024, Duration::from_secs(2));
+        assert_eq!(progress.ratio(), 0.25);
+        assert_eq!(progress.throughput, Some(12.5 * 1024.0 * 1024.0));
+        assert_eq!(progress.eta, Some(Duration::from_secs(6)));
+        assert_eq!(progress.to_string(), "25% 12.5 MiB/s ETA 0:06");
+
+        let progress = Progress::new(0, 0, Duration::ZERO);
+        assert_eq!(progress.ratio(), 0.0);
+        assert_eq!(progress.to_string(), "0%");
+    }
+
+    #[test]
+    fn test_human_bytes() {
+        assert_eq!(human_bytes(512), "512 B");
+        assert_eq!(human_bytes(1536), "1.5 KiB");
+        assert_eq!(human_bytes(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
+}
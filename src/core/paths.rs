@@ -0,0 +1,204 @@
+use std::path::{Component, Path, PathBuf};
+
+use color_eyre::{Result as Res, eyre::bail};
+
+/// Expand a leading `~` to the home directory, `~user` is left as is.
+pub fn expand_tilde(input: &str) -> PathBuf {
+    let home = || std::env::home_dir().filter(|h| !h.as_os_str().is_empty());
+    if input == "~" {
+        if let Some(home) = home() {
+            return home;
+        }
+    } else if let Some(rest) = input
+        .strip_prefix("~/")
+        .or_else(|| input.strip_prefix(format!("~{}", std::path::MAIN_SEPARATOR).as_str()))
+        && let Some(home) = home()
+    {
+        return home.join(rest);
+    }
+    PathBuf::from(input)
+}
+
+/// Expand `$VAR` and `${VAR}`, unknown variables are left as they are.
+pub fn expand_env(input: &str) -> String {
+    let mut res = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(i) = rest.find('$') {
+        res.push_str(&rest[..i]);
+        let after = &rest[i + 1..];
+        // (变量名, `$` 之后占用的长度)
+        let (name, len) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            },
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+        match std::env::var(name).ok().filter(|_| !name.is_empty()) {
+            Some(value) => res.push_str(&value),
+            None => res.push_str(&rest[i..i + 1 + len]),
+        }
+        rest = &after[len..];
+    }
+    res.push_str(rest);
+    res
+}
+
+/// Drop `.` and resolve `..` without touching the filesystem, `..` never goes above the root.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut res = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !res.pop() && !res.has_root() {
+                    res.push("..");
+                }
+            }
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+/// A destination typed by the user: `~` and variables are expanded, and a relative path
+/// is taken from `base`, the directory being sorted. The result does not need to exist.
+pub fn resolve(base: &Path, input: &str) -> PathBuf {
+    normalize(&base.join(expand_tilde(&expand_env(input))))
+}
+
+/// Sub directories completing the last component of `input`, each as the whole new input
+/// ending with a separator, sorted. Hidden ones only if the typed name starts with `.`.
+pub fn complete_dir(base: &Path, input: &str) -> Vec<String> {
+    let split = input.rfind(std::path::is_separator).map_or(0, |i| i + 1);
+    let (dir, prefix) = input.split_at(split);
+    let Ok(entries) = std::fs::read_dir(resolve(base, dir)) else {
+        return vec![];
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_dir())
+        .filter_map(|e| e.file_name().into_string().ok())
+        .filter(|name| {
+            name.starts_with(prefix) && (prefix.starts_with('.') || !name.starts_with('.'))
+        })
+        .collect();
+    // 按名字排序，而不是带分隔符的整个输入
+    names.sort();
+    names
+        .into_iter()
+        .map(|name| format!("{}{}{}", dir, name, std::path::MAIN_SEPARATOR))
+        .collect()
+}
+
+/// Expand `input` and make sure it is an existing directory, returning its absolute path.
+pub fn resolve_dir(input: impl AsRef<Path>) -> Res<PathBuf> {
+    let input = input.as_ref();
+    let path = expand_tilde(&input.to_string_lossy());
+    if !path.exists() {
+        bail!("Path '{}' does not exist.", input.display());
+    }
+    if !path.is_dir() {
+        bail!("Path '{}' is not a directory.", input.display());
+    }
+    Ok(path.canonicalize()?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_expand_tilde() {
+        let Some(home) = std::env::home_dir() else {
+            return;
+        };
+        assert_eq!(expand_tilde("~"), home);
+        assert_eq!(expand_tilde("~/Downloads"), home.join("Downloads"));
+        assert_eq!(expand_tilde("~user/x"), PathBuf::from("~user/x"));
+        assert_eq!(expand_tilde("a/~/b"), PathBuf::from("a/~/b"));
+    }
+
+    #[test]
+    fn test_expand_env() {
+        let (name, value) = std::env::vars()
+            .find(|(k, v)| {
+                !v.is_empty() && k.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            })
+            .expect("no usable environment variable");
+        assert_eq!(expand_env(&format!("${}/x", name)), format!("{}/x", value));
+        assert_eq!(
+            expand_env(&format!("a${{{}}}b", name)),
+            format!("a{}b", value)
+        );
+        assert_eq!(
+            expand_env("$EF_SURELY_UNSET_VAR/x"),
+            "$EF_SURELY_UNSET_VAR/x"
+        );
+        assert_eq!(expand_env("cost $5 ${oops"), "cost $5 ${oops");
+        assert_eq!(expand_env("$"), "$");
+    }
+
+    #[test]
+    fn test_resolve() {
+        let base = Path::new("/data/sort");
+        assert_eq!(resolve(base, "photos"), PathBuf::from("/data/sort/photos"));
+        assert_eq!(resolve(base, "./a/../b"), PathBuf::from("/data/sort/b"));
+        assert_eq!(resolve(base, "../other"), PathBuf::from("/data/other"));
+        assert_eq!(resolve(base, "/abs/x"), PathBuf::from("/abs/x"));
+        assert_eq!(resolve(base, "/../x"), PathBuf::from("/x"));
+        if let Some(home) = std::env::home_dir() {
+            assert_eq!(resolve(base, "~/x"), home.join("x"));
+        }
+    }
+
+    #[test]
+    fn test_complete_dir() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let base = dir.path();
+        for sub in ["photos", "photos-old", "music", ".cache", "photos/2024"] {
+            std::fs::create_dir(base.join(sub))?;
+        }
+        std::fs::write(base.join("photo.txt"), "")?;
+
+        assert_eq!(complete_dir(base, "ph"), ["photos/", "photos-old/"]);
+        assert_eq!(complete_dir(base, ""), ["music/", "photos/", "photos-old/"]);
+        assert_eq!(complete_dir(base, "."), [".cache/"]);
+        assert_eq!(complete_dir(base, ".c"), [".cache/"]);
+        assert_eq!(complete_dir(base, "photos/"), ["photos/2024/"]);
+        let abs = format!("{}/mu", base.display());
+        assert_eq!(
+            complete_dir(base, &abs),
+            [format!("{}/music/", base.display())]
+        );
+        assert!(complete_dir(base, "missing/x").is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_dir() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("file.txt");
+        std::fs::write(&file, "")?;
+
+        assert_eq!(resolve_dir(dir.path())?, dir.path().canonicalize()?);
+        assert!(
+            resolve_dir(&file)
+                .unwrap_err()
+                .to_string()
+                .contains("is not a directory")
+        );
+        assert!(
+            resolve_dir(dir.path().join("missing"))
+                .unwrap_err()
+                .to_string()
+                .contains("does not exist")
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,429 @@
+//! Headless sort plans, see `ef plan export` and `ef plan apply`.
+//!
+//! A plan is what the TUI collects before confirming: the Left/Right/Pending assignments and
+//! the action of each side. The item paths, and relative action targets, are relative to `root`.
+//! Targets may also start with `~` or contain `$VARS`.
+//!
+//! ```toml
+//! root = "/home/me/Downloads"
+//! pending = ["notes.md"]
+//!
+//! [left]
+//! action = "move"
+//! to = "archive"
+//! on_conflict = "rename"
+//! items = ["a.zip", "b.zip"]
+//!
+//! [right]
+//! action = "trash"
+//! items = ["tmp"]
+//! ```
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{
+    Result as Res,
+    eyre::{Context as _, bail},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::core::{
+    cmd::Cmd,
+    file_ops::{self, ConflictPolicy},
+    model::{
+        component::{FileItem, ScrollList, input::InputBox},
+        processor::{InProcess, Processor},
+    },
+    paths,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PlanFormat {
+    Toml,
+    Json,
+}
+
+impl PlanFormat {
+    /// `.json` is json, everything else toml
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => PlanFormat::Json,
+            _ => PlanFormat::Toml,
+        }
+    }
+}
+
+/// The action of one side, mirrors [`InProcess`]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum PlanAction {
+    #[default]
+    None,
+    Delete,
+    Trash,
+    Organize {
+        to: PathBuf,
+    },
+    Move {
+        to: PathBuf,
+    },
+    Copy {
+        to: PathBuf,
+    },
+    Zip {
+        to: PathBuf,
+    },
+    Extract {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        to: Option<PathBuf>,
+    },
+    Rename {
+        rule: String,
+    },
+}
+
+impl PlanAction {
+    /// The action chosen in the TUI, a side still waiting for one does nothing
+    pub(crate) fn from_proc(proc: &InProcess) -> Self {
+        let to = |input: &InputBox| PathBuf::from(input.input());
+        match proc {
+            InProcess::Waiting | InProcess::None => PlanAction::None,
+            InProcess::Delete => PlanAction::Delete,
+            InProcess::Trash => PlanAction::Trash,
+            InProcess::Organize(input) => PlanAction::Organize { to: to(input) },
+            InProcess::Move(input) => PlanAction::Move { to: to(input) },
+            InProcess::Copy(input) => PlanAction::Copy { to: to(input) },
+            InProcess::Zip(input) => PlanAction::Zip { to: to(input) },
+            InProcess::Extract(input) if input.input().is_empty() => {
+                PlanAction::Extract { to: None }
+            }
+            InProcess::Extract(input) => PlanAction::Extract {
+                to: Some(to(input)),
+            },
+            InProcess::Rename(rule) => PlanAction::Rename {
+                rule: rule.input().to_string(),
+            },
+        }
+    }
+
+    /// Targets are resolved later against the plan root, see [`Processor::proc_into_cmd`]
+    fn to_proc(&self) -> Res<InProcess> {
+        let target = |to: &Path| -> Res<InputBox> {
+            if to.as_os_str().is_empty() {
+                bail!("'{:?}' needs a target", self);
+            }
+            Ok(InputBox::from(to.to_string_lossy().as_ref()))
+        };
+        Ok(match self {
+            PlanAction::None => InProcess::None,
+            PlanAction::Delete => InProcess::Delete,
+            PlanAction::Trash => InProcess::Trash,
+            PlanAction::Organize { to } => InProcess::Organize(target(to)?),
+            PlanAction::Move { to } => InProcess::Move(target(to)?),
+            PlanAction::Copy { to } => InProcess::Copy(target(to)?),
+            PlanAction::Zip { to } => InProcess::Zip(target(to)?),
+            PlanAction::Extract { to: None } => InProcess::Extract(InputBox::new()),
+            PlanAction::Extract { to: Some(to) } => InProcess::Extract(target(to)?),
+            PlanAction::Rename { rule } => InProcess::Rename(InputBox::from(rule.as_str())),
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlanSide {
+    #[serde(flatten)]
+    pub action: PlanAction,
+    /// for organize, move and copy, the config default if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_conflict: Option<ConflictPolicy>,
+    #[serde(default)]
+    pub items: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Plan {
+    pub root: PathBuf,
+    #[serde(default)]
+    pub pending: Vec<PathBuf>,
+    #[serde(default)]
+    pub left: PlanSide,
+    #[serde(default)]
+    pub right: PlanSide,
+}
+
+impl Plan {
+    /// Where the TUI saves its plan, next to the journal
+    pub fn default_path() -> Option<PathBuf> {
+        crate::core::config::state_dir().map(|dir| dir.join("plan.toml"))
+    }
+
+    /// Every item of `root` pending, sorted by name, with no action on either side.
+    /// Used by `ef plan export`, the TUI saves its current state instead,
+    /// see [`Processor::to_plan`]
+    pub fn from_dir(root: &Path, show_hidden: bool, respect_gitignore: bool) -> Res<Self> {
+        let mut pending: Vec<PathBuf> = file_ops::list_items(
+            root,
+            show_hidden,
+            respect_gitignore,
+            file_ops::ViewMode::Flat,
+        )?
+        .into_iter()
+        .map(|item| PathBuf::from(item.display_name))
+        .collect();
+        pending.sort();
+        Ok(Self {
+            root: root.to_path_buf(),
+            pending,
+            left: PlanSide::default(),
+            right: PlanSide::default(),
+        })
+    }
+
+    pub fn parse_from_str(str: &str, format: PlanFormat) -> Res<Self> {
+        match format {
+            PlanFormat::Toml => toml::from_str(str).map_err(|e| e.into()),
+            PlanFormat::Json => serde_json::from_str(str).map_err(|e| e.into()),
+        }
+    }
+
+    pub fn parse_from_path(path: &Path) -> Res<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read plan '{}'", path.display()))?;
+        Self::parse_from_str(&content, PlanFormat::from_path(path))
+            .with_context(|| format!("Failed to parse plan '{}'", path.display()))
+    }
+
+    pub fn to_string(&self, format: PlanFormat) -> Res<String> {
+        Ok(match format {
+            PlanFormat::Toml => toml::to_string_pretty(self)?,
+            PlanFormat::Json => serde_json::to_string_pretty(self)? + "\n",
+        })
+    }
+
+    /// Write the plan to `path`, creating its directory
+    pub fn save(&self, path: &Path, format: PlanFormat) -> Res<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, self.to_string(format)?)
+            .with_context(|| format!("Failed to write plan '{}'", path.display()))
+    }
+
+    /// Validate the plan and turn it into the commands the TUI would run on confirm,
+    /// left side first. `Seq` and `Batch` are flattened.
+    /// `policy` applies to the sides without `on_conflict`, nobody answers an `ask` here.
+    pub fn to_cmds(&self, policy: ConflictPolicy) -> Res<Vec<Cmd>> {
+        let root = paths::resolve_dir(paths::expand_tilde(&self.root.to_string_lossy()))
+            .context("Invalid plan root")?;
+
+        let mut seen = HashSet::new();
+        for item in self
+            .pending
+            .iter()
+            .chain(&self.left.items)
+            .chain(&self.right.items)
+        {
+            let path = root.join(item);
+            if !seen.insert(path.clone()) {
+                bail!("'{}' is listed more than once", item.display());
+            }
+            if path.symlink_metadata().is_err() {
+                bail!("'{}' does not exist", path.display());
+            }
+        }
+
+        let mut next_id = 0;
+        let mut cmds = Vec::new();
+        for (name, side) in [("left", &self.left), ("right", &self.right)] {
+            let proc = side
+                .action
+                .to_proc()
+                .with_context(|| format!("Invalid {} side", name))?;
+            let list = side_list(&root, &side.items);
+            let policy = side.on_conflict.unwrap_or(policy);
+            match Processor::proc_into_cmd(&proc, &list, policy, &root, &mut next_id) {
+                Cmd::Error(e) => bail!("Invalid {} side: {}", name, e),
+                cmd => flatten(cmd, &mut cmds),
+            }
+        }
+        Ok(cmds)
+    }
+}
+
+fn side_list(root: &Path, items: &[PathBuf]) -> ScrollList {
+    ScrollList::new(
+        items
+            .iter()
+            .enumerate()
+            .map(|(id, item)| {
+                let path = root.join(item);
+                FileItem {
+                    id: id as u64,
+                    display_name: path
+                        .file_name()
+                        .map(|n| n.to_string_lossy().to_string())
+                        .unwrap_or_default(),
+                    is_dir: path.is_dir(),
+                    path,
+                }
+            })
+            .collect(),
+    )
+}
+
+fn flatten(cmd: Cmd, out: &mut Vec<Cmd>) {
+    match cmd {
+        Cmd::None => {}
+        Cmd::Seq(cmds) | Cmd::Batch(cmds) => cmds.into_iter().for_each(|c| flatten(c, out)),
+        cmd => out.push(cmd),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn setup() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            std::fs::write(root.join(name), name).unwrap();
+        }
+        (dir, root)
+    }
+
+    #[test]
+    fn test_plan_round_trip() -> Res<()> {
+        let (_dir, root) = setup();
+        let mut plan = Plan::from_dir(&root, false, true)?;
+        assert_eq!(plan.pending, ["a.txt", "b.txt", "c.txt"].map(PathBuf::from));
+
+        plan.left = PlanSide {
+            action: PlanAction::Move { to: "dst".into() },
+            on_conflict: Some(ConflictPolicy::Rename),
+            items: plan.pending.drain(..1).collect(),
+        };
+        plan.right = PlanSide {
+            action: PlanAction::Extract { to: None },
+            on_conflict: None,
+            items: vec![],
+        };
+        for format in [PlanFormat::Toml, PlanFormat::Json] {
+            let text = plan.to_string(format)?;
+            assert_eq!(Plan::parse_from_str(&text, format)?, plan);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_parse_toml() -> Res<()> {
+        let plan = Plan::parse_from_str(
+            r#"root = "/tmp"
+
+[left]
+action = "rename"
+rule = "addprefix \"x_\""
+items = ["a.txt"]
+"#,
+            PlanFormat::Toml,
+        )?;
+        assert_eq!(
+            plan.left.action,
+            PlanAction::Rename {
+                rule: "addprefix \"x_\"".to_string()
+            }
+        );
+        assert_eq!(plan.right, PlanSide::default());
+        assert!(
+            Plan::parse_from_str("root = \"/\"\n[left]\naction = \"burn\"", PlanFormat::Toml)
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_to_cmds() -> Res<()> {
+        let (_dir, root) = setup();
+        let plan = Plan {
+            root: root.clone(),
+            pending: vec!["c.txt".into()],
+            left: PlanSide {
+                action: PlanAction::Move { to: "dst".into() },
+                on_conflict: None,
+                items: vec!["a.txt".into()],
+            },
+            right: PlanSide {
+                action: PlanAction::Rename {
+                    rule: "addprefix \"old_\"".to_string(),
+                },
+                on_conflict: None,
+                items: vec!["b.txt".into()],
+            },
+        };
+        let cmds = plan.to_cmds(ConflictPolicy::Skip)?;
+        assert_eq!(cmds.len(), 2);
+        assert!(
+            matches!(&cmds[0], Cmd::AsyncMove(_, items, to, ConflictPolicy::Skip) if items == &[root.join("a.txt")] && to == &root.join("dst"))
+        );
+        assert!(
+            matches!(&cmds[1], Cmd::AsyncRename(_, pairs) if pairs == &[(root.join("b.txt"), root.join("old_b.txt"))])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_validation() {
+        let (_dir, root) = setup();
+        let plan = |left: PlanSide, pending: &[&str]| Plan {
+            root: root.clone(),
+            pending: pending.iter().map(PathBuf::from).collect(),
+            left,
+            right: PlanSide::default(),
+        };
+        let side = |action, items: &[&str]| PlanSide {
+            action,
+            on_conflict: None,
+            items: items.iter().map(PathBuf::from).collect(),
+        };
+
+        // listed twice
+        assert!(
+            plan(side(PlanAction::Trash, &["a.txt"]), &["a.txt"])
+                .to_cmds(ConflictPolicy::Ask)
+                .is_err()
+        );
+        // missing item
+        assert!(
+            plan(side(PlanAction::Trash, &["nope.txt"]), &[])
+                .to_cmds(ConflictPolicy::Ask)
+                .is_err()
+        );
+        // empty target
+        assert!(
+            plan(side(PlanAction::Copy { to: "".into() }, &["a.txt"]), &[])
+                .to_cmds(ConflictPolicy::Ask)
+                .is_err()
+        );
+        // bad rule, and a rename onto an existing file
+        let rename = |rule: &str| PlanAction::Rename {
+            rule: rule.to_string(),
+        };
+        assert!(
+            plan(side(rename("nonsense"), &["a.txt"]), &[])
+                .to_cmds(ConflictPolicy::Ask)
+                .is_err()
+        );
+        assert!(
+            plan(side(rename("replace \"a\" \"b\""), &["a.txt"]), &[])
+                .to_cmds(ConflictPolicy::Ask)
+                .is_err()
+        );
+        // missing root
+        let mut missing = plan(PlanSide::default(), &[]);
+        missing.root = root.join("missing");
+        assert!(missing.to_cmds(ConflictPolicy::Ask).is_err());
+    }
+}
@@ -0,0 +1,106 @@
+//! Recently used destination directories, offered first when completing a path
+
+use color_eyre::{Result as Res, eyre::Context as _};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// 最多记录的目录数
+const MAX_RECENT: usize = 20;
+
+#[derive(Debug, Default, Clone)]
+pub struct RecentDirs {
+    path: PathBuf,
+    /// most recent first
+    entries: Vec<PathBuf>,
+}
+
+impl RecentDirs {
+    /// A missing or unreadable file gives an empty list
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let entries = match Self::read(&path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                tracing::warn!("Ignore recent dirs: {:?}", e);
+                vec![]
+            }
+        };
+        Self { path, entries }
+    }
+
+    fn read(path: &Path) -> Res<Vec<PathBuf>> {
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read '{}'", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Corrupted recent dirs '{}'", path.display()))
+    }
+
+    /// `recent_dirs.json` in the state dir, None if it cannot be determined
+    pub fn open_default() -> Option<Self> {
+        crate::core::config::state_dir().map(|dir| Self::load(dir.join("recent_dirs.json")))
+    }
+
+    pub fn entries(&self) -> &[PathBuf] {
+        &self.entries
+    }
+
+    /// Put `dir` in front, dropping the oldest ones beyond the limit
+    pub fn push(&mut self, dir: PathBuf) {
+        self.entries.retain(|x| *x != dir);
+        self.entries.insert(0, dir);
+        self.entries.truncate(MAX_RECENT);
+    }
+
+    pub fn save(&self) -> Res<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, serde_json::to_string(&self.entries)?)
+            .with_context(|| format!("Failed to write '{}'", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_push_save_load() -> Res<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("state/recent_dirs.json");
+
+        let mut recent = RecentDirs::load(&path);
+        assert!(recent.entries().is_empty());
+        for i in 0..MAX_RECENT + 2 {
+            recent.push(PathBuf::from(format!("/d{}", i)));
+        }
+        recent.push(PathBuf::from("/d5"));
+        recent.save()?;
+
+        let loaded = RecentDirs::load(&path);
+        assert_eq!(loaded.entries().len(), MAX_RECENT);
+        assert_eq!(loaded.entries()[0], PathBuf::from("/d5"));
+        assert_eq!(
+            loaded.entries()[1],
+            PathBuf::from(format!("/d{}", MAX_RECENT + 1))
+        );
+        assert_eq!(
+            loaded
+                .entries()
+                .iter()
+                .filter(|x| x.ends_with("d5"))
+                .count(),
+            1
+        );
+        assert!(!loaded.entries().contains(&PathBuf::from("/d0")));
+
+        fs::write(&path, "not json")?;
+        assert!(RecentDirs::load(&path).entries().is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,301 @@
+//! Dry-run reports: what a batch of commands would do to the filesystem.
+
+use std::{collections::HashSet, path::PathBuf};
+
+use crate::core::{
+    cmd::Cmd,
+    file_ops::{self, Conflict},
+    journal::FsOp,
+    runner,
+    service::tasks::{CancelToken, ProgressFn},
+};
+
+/// Every filesystem mutation of the reported commands, in order, and the predicted conflicts
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DryRunReport {
+    pub ops: Vec<FsOp>,
+    pub conflicts: Vec<Conflict>,
+}
+
+impl DryRunReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn of(cmd: &Cmd) -> Self {
+        let mut report = Self::new();
+        report.add(cmd);
+        report
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty() && self.conflicts.is_empty()
+    }
+
+    /// Append `cmd`, looking into `Seq` and `Batch`.
+    /// A destination created by an earlier command counts as existing,
+    /// except for directories, which are reused.
+    pub fn add(&mut self, cmd: &Cmd) {
+        if let Cmd::Seq(cmds) | Cmd::Batch(cmds) = cmd {
+            cmds.iter().for_each(|cmd| self.add(cmd));
+            return;
+        }
+
+        let planned: HashSet<PathBuf> = self.ops.iter().filter_map(target_of).collect();
+        let mut ops = FsOp::plan(cmd);
+        ops.retain(|op| !matches!(op, FsOp::Mkdir { path } if planned.contains(path)));
+        self.conflicts.extend(
+            ops.iter()
+                .filter_map(target_of)
+                .filter(|path| planned.contains(path))
+                .map(Conflict::DestinationExists),
+        );
+        self.conflicts.extend(conflicts_of(cmd));
+        self.ops.extend(ops);
+    }
+
+    /// One line per conflict, so that they are seen first, then one per operation
+    pub fn lines(&self) -> Vec<String> {
+        self.conflicts
+            .iter()
+            .map(|c| format!("conflict: {}", c))
+            .chain(self.ops.iter().map(|op| op.to_string()))
+            .collect()
+    }
+}
+
+impl std::fmt::Display for DryRunReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.lines()
+            .iter()
+            .try_for_each(|line| writeln!(f, "{}", line))
+    }
+}
+
+/// What one file command really did
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub done: Vec<FsOp>,
+    /// planned but not performed
+    pub not_done: Vec<FsOp>,
+    /// the error chain, outermost first
+    pub error: Option<Vec<String>>,
+}
+
+impl Outcome {
+    /// Execute `cmd`, whose planned operations are `ops`, see [`FsOp::plan`]
+    pub fn execute(
+        cmd: Cmd,
+        ops: &[FsOp],
+        reporter: Option<ProgressFn>,
+        cancel: Option<&CancelToken>,
+    ) -> Self {
+        // 执行前就已经成立的不算
+        let before = FsOp::applied(ops);
+        let res = runner::execute(cmd, reporter, cancel);
+        let (done, not_done) = ops
+            .iter()
+            .zip(before.into_iter().zip(FsOp::applied(ops)))
+            .map(|(op, (before, after))| (op.clone(), !before && after))
+            .partition::<Vec<_>, _>(|(_, applied)| *applied);
+        Self {
+            done: done.into_iter().map(|(op, _)| op).collect(),
+            not_done: not_done.into_iter().map(|(op, _)| op).collect(),
+            error: res
+                .err()
+                .map(|e| e.chain().map(|cause| cause.to_string()).collect()),
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// The error chain in one line
+    pub fn result(&self) -> Result<(), String> {
+        match &self.error {
+            Some(chain) => Err(chain.join(": ")),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The path an operation creates
+fn target_of(op: &FsOp) -> Option<PathBuf> {
+    match op {
+        FsOp::Mkdir { path } | FsOp::Create { path } => Some(path.clone()),
+        FsOp::Move { to, .. } | FsOp::Copy { to, .. } | FsOp::Rename { to, .. } => Some(to.clone()),
+        FsOp::Trash { .. } | FsOp::Delete { .. } | FsOp::Overwrite { .. } => None,
+    }
+}
+
+/// The same checks the `file_ops` functions run before doing anything
+fn conflicts_of(cmd: &Cmd) -> Vec<Conflict> {
+    match cmd {
+        Cmd::Organize(items, dir, policy) | Cmd::AsyncOrganize(_, items, dir, policy) => {
+            file_ops::check_organize(items, dir, *policy)
+        }
+        Cmd::Move(items, dir, policy)
+        | Cmd::AsyncMove(_, items, dir, policy)
+        | Cmd::Copy(items, dir, policy)
+        | Cmd::AsyncCopy(_, items, dir, policy) => file_ops::check_into_dir(items, dir, *policy),
+        Cmd::Rename(pairs) | Cmd::AsyncRename(_, pairs) => file_ops::check_rename(pairs),
+        Cmd::Delete(items)
+        | Cmd::AsyncDelete(_, items)
+        | Cmd::Trash(items)
+        | Cmd::AsyncTrash(_, items) => file_ops::check_sources(items),
+        Cmd::AsyncZip(_, items, archive_path) => {
+            let mut conflicts = file_ops::check_sources(items);
+            if archive_path.exists() {
+                conflicts.push(Conflict::DestinationExists(archive_path.clone()));
+            }
+            conflicts
+        }
+        Cmd::AsyncExtract(_, archive_path, _) => {
+            file_ops::check_sources(std::slice::from_ref(archive_path))
+        }
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::file_ops::ConflictPolicy;
+
+    #[test]
+    fn test_report_ops_and_conflicts() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("a.txt"), "").unwrap();
+        std::fs::create_dir(root.join("sub")).unwrap();
+        std::fs::write(root.join("sub").join("a.txt"), "").unwrap();
+        std::fs::create_dir(root.join("taken")).unwrap();
+        std::fs::write(root.join("taken").join("a.txt"), "").unwrap();
+
+        let report = DryRunReport::of(&Cmd::Batch(vec![
+            Cmd::Move(
+                vec![root.join("a.txt")],
+                root.join("dst"),
+                ConflictPolicy::Ask,
+            ),
+            Cmd::Copy(
+                vec![root.join("a.txt"), root.join("sub").join("a.txt")],
+                root.join("taken"),
+                ConflictPolicy::Ask,
+            ),
+            Cmd::Trash(vec![root.join("gone")]),
+        ]));
+
+        assert_eq!(
+            report.ops[..2],
+            [
+                FsOp::Mkdir {
+                    path: root.join("dst")
+                },
+                FsOp::Move {
+                    from: root.join("a.txt"),
+                    to: root.join("dst").join("a.txt"),
+                },
+            ]
+        );
+        assert_eq!(
+            report.conflicts,
+            [
+                // the second a.txt is numbered
+                Conflict::DestinationExists(root.join("taken").join("a.txt")),
+                Conflict::SourceMissing(root.join("gone")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_report_sees_earlier_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("a.txt"), "").unwrap();
+        std::fs::write(root.join("b.txt"), "").unwrap();
+
+        let mut report = DryRunReport::new();
+        report.add(&Cmd::Organize(
+            vec![root.join("a.txt")],
+            root.join("dst"),
+            ConflictPolicy::Ask,
+        ));
+        assert!(report.conflicts.is_empty());
+        // the directory is reused
+        report.add(&Cmd::Copy(
+            vec![root.join("b.txt")],
+            root.join("dst"),
+            ConflictPolicy::Ask,
+        ));
+        assert!(report.conflicts.is_empty());
+        assert_eq!(report.ops.len(), 3);
+        report.add(&Cmd::Copy(
+            vec![root.join("a.txt")],
+            root.join("dst"),
+            ConflictPolicy::Ask,
+        ));
+        assert_eq!(
+            report.conflicts,
+            [Conflict::DestinationExists(root.join("dst").join("a.txt"))]
+        );
+        assert!(report.lines()[0].starts_with("conflict: "));
+    }
+
+    #[test]
+    fn test_report_organize_needs_new_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("a.txt"), "").unwrap();
+        std::fs::create_dir(root.join("dst")).unwrap();
+        let items = vec![root.join("a.txt")];
+
+        let organize = DryRunReport::of(&Cmd::Organize(
+            items.clone(),
+            root.join("dst"),
+            ConflictPolicy::Ask,
+        ));
+        assert_eq!(
+            organize.conflicts,
+            [Conflict::DestinationExists(root.join("dst"))]
+        );
+        let moved = DryRunReport::of(&Cmd::Move(items, root.join("dst"), ConflictPolicy::Ask));
+        assert!(moved.conflicts.is_empty());
+        assert_eq!(
+            moved.ops,
+            [FsOp::Move {
+                from: root.join("a.txt"),
+                to: root.join("dst").join("a.txt"),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_outcome_of_partial_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("a.txt"), "").unwrap();
+        std::fs::write(root.join("b.txt"), "").unwrap();
+
+        let cmd = Cmd::Rename(vec![
+            (root.join("a.txt"), root.join("c.txt")),
+            (root.join("b.txt"), root.join("d.txt")),
+        ]);
+        let ops = FsOp::plan(&cmd);
+        // the second source disappears after planning
+        std::fs::remove_file(root.join("b.txt")).unwrap();
+
+        let outcome = Outcome::execute(cmd, &ops, None, None);
+        assert!(!outcome.is_ok());
+        assert!(outcome.done.is_empty());
+        assert_eq!(outcome.not_done, ops);
+        assert!(outcome.result().unwrap_err().contains("b.txt"));
+
+        let cmd = Cmd::Rename(vec![(root.join("a.txt"), root.join("c.txt"))]);
+        let ops = FsOp::plan(&cmd);
+        let outcome = Outcome::execute(cmd, &ops, None, None);
+        assert_eq!(outcome.done, ops);
+        assert_eq!(outcome.result(), Ok(()));
+    }
+}
@@ -1,11 +1,24 @@
-use std::{env::current_dir, fmt::Debug, sync::Arc};
+use std::{
+    env::current_dir,
+    fmt::Debug,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
 
 use crate::core::{
     cmd::Cmd,
     context::Context,
-    model::{processor::Processor, selector::SelectModel},
-    msg::Msg,
-    service::servicer::Servicer,
+    fop::archive,
+    journal::{FsOp, Journal},
+    model::{processor::Processor, results::ResultsModel, selector::SelectModel},
+    msg::{Msg, TaskState, TaskStatus},
+    plan::{Plan, PlanFormat},
+    recent::RecentDirs,
+    report::{DryRunReport, Outcome},
+    service::{
+        servicer::Servicer,
+        tasks::{CancelToken, ProgressFn},
+    },
 };
 
 use crate::core::file_ops;
@@ -81,6 +94,18 @@ pub struct Runner {
     context: Context,
     should_exit: bool,
     dry_run: bool,
+    /// None if there is no state dir to keep it in
+    journal: Option<Journal>,
+    /// journal batch of the message being handled, unless a command brings its own
+    batch: u64,
+    /// everything skipped in dry run
+    report: DryRunReport,
+    /// every file command performed, async tasks push theirs when done
+    outcomes: Arc<Mutex<Vec<Outcome>>>,
+    /// the results screen is shown once on exit if anything failed
+    showing_results: bool,
+    /// where the last plan was saved, printed on exit
+    saved_plan: Option<PathBuf>,
 }
 
 impl Runner {
@@ -92,6 +117,12 @@ impl Runner {
             servicer: Servicer::new(tick_rate, 8),
             guard_model: Default::default(),
             should_exit: false,
+            journal: Journal::open_default(),
+            batch: 0,
+            report: DryRunReport::new(),
+            outcomes: Default::default(),
+            showing_results: false,
+            saved_plan: None,
         }
     }
 
@@ -100,12 +131,107 @@ impl Runner {
         self
     }
 
-    fn submit_task<F>(&mut self, task_fn: F, id: u64)
+    /// What would have been done, None if not in dry run
+    pub fn dry_run_report(&self) -> Option<&DryRunReport> {
+        self.dry_run.then_some(&self.report)
+    }
+
+    pub fn saved_plan(&self) -> Option<&Path> {
+        self.saved_plan.as_deref()
+    }
+
+    /// Whether any file operation failed
+    pub fn failed(&self) -> bool {
+        self.outcomes().iter().any(|o| !o.is_ok())
+    }
+
+    fn outcomes(&self) -> Vec<Outcome> {
+        self.outcomes
+            .lock()
+            .map(|o| o.clone())
+            .unwrap_or_else(|e| e.into_inner().clone())
+    }
+
+    /// `ops` are journaled once the task is finished
+    fn submit_task<F>(&mut self, task_fn: F, id: u64, ops: Vec<FsOp>)
     where
-        F: FnOnce(Arc<dyn Fn(f32) + Send + Sync>) -> Result<(), String> + Send + 'static,
+        F: FnOnce(ProgressFn, CancelToken) -> Result<(), String> + Send + 'static,
     {
         let epoch = self.guard_model.curr_epoch;
-        self.servicer.task_manager.submit(id, epoch, task_fn);
+        let journal = self.journal.clone();
+        let batch = self.batch;
+        self.servicer
+            .task_manager
+            .submit(id, epoch, move |reporter, cancel| {
+                let res = task_fn(reporter, cancel);
+                if let Some(journal) = journal
+                    && let Err(e) = journal.record(batch, ops)
+                {
+                    tracing::error!("Failed to record journal: {:?}", e);
+                }
+                res
+            });
+    }
+
+    fn push_outcome(&self, outcome: Outcome) {
+        if let Ok(mut outcomes) = self.outcomes.lock() {
+            outcomes.push(outcome);
+        }
+    }
+
+    fn record(&self, ops: Vec<FsOp>) {
+        if let Some(journal) = &self.journal
+            && let Err(e) = journal.record(self.batch, ops)
+        {
+            tracing::error!("Failed to record journal: {:?}", e);
+        }
+    }
+
+    /// Reverse the last journaled batch
+    fn undo_last_batch(&self) {
+        let Some(journal) = &self.journal else {
+            tracing::error!("Undo failed: no journal available");
+            return;
+        };
+        if self.dry_run {
+            match journal.last_batch() {
+                Ok(Some((_, ops))) => ops
+                    .iter()
+                    .rev()
+                    .for_each(|op| tracing::info!("undo: {}", op)),
+                Ok(None) => tracing::info!("undo: nothing to undo"),
+                Err(e) => tracing::error!("Undo failed: {:?}", e),
+            }
+            return;
+        }
+        match journal.undo_last() {
+            Ok(Some(report)) => {
+                tracing::info!("undo batch {}", report.batch);
+                report
+                    .undone
+                    .iter()
+                    .for_each(|op| tracing::info!("undone: {}", op));
+                report
+                    .failed
+                    .iter()
+                    .for_each(|(op, e)| tracing::error!("Failed to undo {}: {}", op, e));
+            }
+            Ok(None) => tracing::info!("undo: nothing to undo"),
+            Err(e) => tracing::error!("Undo failed: {:?}", e),
+        }
+    }
+
+    /// In dry run no task is submitted, so tell the model it finished right away.
+    fn skip_task(&mut self, id: u64) {
+        let msg = Msg::TaskState(TaskState::new(
+            id,
+            self.guard_model.curr_epoch,
+            TaskStatus::Completed,
+        ));
+        let envelope = self
+            .guard_model
+            .update(EpochEnvelope::new(msg), &self.context);
+        self.handle_cmd(envelope);
     }
 
     pub async fn run(&mut self, term: &mut DefaultTerminal) -> Res<()> {
@@ -121,6 +247,7 @@ impl Runner {
             init_path.clone(),
             self.context.config.show_hidden,
             self.context.config.respect_gitignore,
+            self.context.config.view_mode(),
         )?));
 
         // init service
@@ -157,6 +284,7 @@ impl Runner {
 
     /// 理论上来说应该按需重绘，但是无所谓了
     fn handle_msg(&mut self, msg: Msg) -> bool {
+        self.batch = Journal::new_batch();
         let envelope = self
             .guard_model
             .update(EpochEnvelope::new(msg), &self.context);
@@ -165,7 +293,14 @@ impl Runner {
     }
 
     fn handle_cmd(&mut self, envelope: EpochEnvelope<Cmd>) {
+        // async commands take these into their task, sync ones are journaled below
+        let mut ops = FsOp::plan(&envelope.payload);
         match envelope.payload {
+            Cmd::Exit if !self.showing_results && self.failed() => {
+                self.showing_results = true;
+                self.guard_model
+                    .change_model(Box::new(ResultsModel::new(self.outcomes())));
+            }
             Cmd::Exit => {
                 self.should_exit = true;
             }
@@ -173,85 +308,57 @@ impl Runner {
                 tracing::error!("{:?}", e);
             }
             Cmd::IntoProcess(m) => {
-                self.guard_model.change_model(Box::new(Processor::new(m)));
-            }
-            Cmd::Organize(items, target_path) => {
-                tracing::info!("organize:{:?}->{:?}", &items, &target_path);
-                if !self.dry_run
-                    && let Err(e) = file_ops::organize(&items, &target_path) {
-                        tracing::error!("Organize failed: {:?}", e);
-                    }
-            }
-            Cmd::Copy(items, target_path) => {
-                tracing::info!("copy:{:?}->{:?}", &items, &target_path);
-                if !self.dry_run
-                    && let Err(e) = file_ops::copy(&items, target_path, None) {
-                        tracing::error!("Copy failed: {:?}", e);
-                    }
-            }
-            Cmd::Move(items, target_path) => {
-                tracing::info!("move:{:?}->{:?}", &items, &target_path);
-                if !self.dry_run
-                    && let Err(e) = file_ops::organize(&items, &target_path) {
-                        tracing::error!("Move failed: {:?}", e);
-                    }
-            }
-            Cmd::Delete(items) => {
-                tracing::info!("delete:{:?}", &items);
-                if !self.dry_run
-                    && let Err(e) = crate::core::file_ops::delete(&items) {
-                        tracing::error!("Delete failed: {:?}", e);
-                    }
+                self.guard_model.change_model(Box::new(Processor::new(
+                    m,
+                    self.context.config.conflict_policy,
+                    RecentDirs::open_default(),
+                )));
             }
-            Cmd::Trash(items) => {
-                tracing::info!("trash:{:?}", &items);
-                if !self.dry_run
-                    && let Err(e) = crate::core::file_ops::trash(&items) {
-                        tracing::error!("Trash failed: {:?}", e);
+            cmd @ (Cmd::Organize(..)
+            | Cmd::Copy(..)
+            | Cmd::Move(..)
+            | Cmd::Rename(..)
+            | Cmd::Delete(..)
+            | Cmd::Trash(..)) => {
+                tracing::info!("{:?}", &cmd);
+                if self.dry_run {
+                    self.report.add(&cmd);
+                } else {
+                    let outcome = Outcome::execute(cmd, &ops, None, None);
+                    if let Err(e) = outcome.result() {
+                        tracing::error!("{}", e);
                     }
-            }
-            Cmd::AsyncDelete(id, items) => {
-                tracing::info!("async delete:{:?}", &items);
-                if !self.dry_run {
-                    self.submit_task(
-                        move |_| crate::core::file_ops::delete(&items).map_err(|e| e.to_string()),
-                        id,
-                    );
-                }
-            }
-            Cmd::AsyncOrganize(id, items, target_path) => {
-                tracing::info!("async organize:{:?}->{:?}", &items, &target_path);
-                if !self.dry_run {
-                    self.submit_task(
-                        move |_| file_ops::organize(&items, &target_path).map_err(|e| e.to_string()),
-                        id,
-                    );
+                    self.push_outcome(outcome);
                 }
             }
-            Cmd::AsyncCopy(id, items, target_path) => {
-                tracing::info!("async copy:{:?}->{:?}", &items, &target_path);
-                if !self.dry_run {
+            cmd @ (Cmd::AsyncOrganize(..)
+            | Cmd::AsyncDelete(..)
+            | Cmd::AsyncCopy(..)
+            | Cmd::AsyncTrash(..)
+            | Cmd::AsyncMove(..)
+            | Cmd::AsyncZip(..)
+            | Cmd::AsyncExtract(..)
+            | Cmd::AsyncRename(..)) => {
+                tracing::info!("{:?}", &cmd);
+                let id = cmd.task_ids()[0];
+                if self.dry_run {
+                    self.report.add(&cmd);
+                    self.skip_task(id);
+                } else {
+                    let outcomes = Arc::clone(&self.outcomes);
+                    let planned = ops.clone();
                     self.submit_task(
-                        move |reporter| file_ops::copy(&items, target_path, Some(reporter)).map_err(|e| e.to_string()),
-                        id,
-                    );
-                }
-            }
-            Cmd::AsyncMove(id, items, target_path) => {
-                tracing::info!("async move:{:?}->{:?}", &items, &target_path);
-                if !self.dry_run {
-                    self.submit_task(
-                        move |_| file_ops::organize(&items, &target_path).map_err(|e| e.to_string()),
-                        id,
-                    );
-                }
-            }
-            Cmd::AsyncTrash(id, items) => {
-                tracing::info!("async trash:{:?}", &items);
-                if !self.dry_run {
-                    self.submit_task(
-                        move |_| crate::core::file_ops::trash(&items).map_err(|e| e.to_string()),
+                        move |reporter, cancel| {
+                            let outcome =
+                                Outcome::execute(cmd, &planned, Some(reporter), Some(&cancel));
+                            let res = outcome.result();
+                            if let Ok(mut outcomes) = outcomes.lock() {
+                                outcomes.push(outcome);
+                            }
+                            res
+                        },
                         id,
+                        std::mem::take(&mut ops),
                     );
                 }
             }
@@ -265,6 +372,11 @@ impl Runner {
                     self.handle_cmd(EpochEnvelope::new(cmd));
                 });
             }
+            Cmd::InBatch(batch, cmd) => {
+                let outer = std::mem::replace(&mut self.batch, batch);
+                self.handle_cmd(EpochEnvelope::new(*cmd));
+                self.batch = outer;
+            }
             Cmd::ToggleShowHidden => {
                 self.context.config.show_hidden = !self.context.config.show_hidden;
                 tracing::info!("Toggle show_hidden: {}", self.context.config.show_hidden);
@@ -276,11 +388,16 @@ impl Runner {
                     self.context.config.respect_gitignore
                 );
             }
+            Cmd::ToggleRecursive => {
+                self.context.config.recursive = !self.context.config.recursive;
+                tracing::info!("Toggle recursive: {}", self.context.config.recursive);
+            }
             Cmd::LoadDir(path) => {
                 match file_ops::list_items(
                     &path,
                     self.context.config.show_hidden,
                     self.context.config.respect_gitignore,
+                    self.context.config.view_mode(),
                 ) {
                     Ok(items) => {
                         let msg = Msg::DirLoaded(path, items);
@@ -293,8 +410,32 @@ impl Runner {
                 }
             }
 
+            Cmd::ChangeWatchPath(path) => self.servicer.change_watch_path(path),
+
+            Cmd::Undo => self.undo_last_batch(),
+
+            Cmd::SavePlan(plan) => match Plan::default_path() {
+                Some(path) => match plan.save(&path, PlanFormat::Toml) {
+                    Ok(()) => {
+                        tracing::info!("saved plan to {:?}", path);
+                        self.saved_plan = Some(path);
+                    }
+                    Err(e) => tracing::error!("{:?}", e),
+                },
+                None => tracing::error!("no state dir to save the plan in"),
+            },
+
+            Cmd::CancelTask(id) => {
+                let running = self.servicer.task_manager.cancel(id);
+                tracing::info!("cancel task {}, running: {}", id, running);
+            }
+
             _ => {}
         }
+
+        if !self.dry_run {
+            self.record(ops);
+        }
     }
 
     fn draw(&mut self, term: &mut DefaultTerminal) -> Res<()> {
@@ -302,3 +443,31 @@ impl Runner {
         Ok(())
     }
 }
+
+/// Perform the file operation of `cmd` right away, an `Async*` one runs on the calling thread.
+/// Commands that are not file operations are ignored.
+pub fn execute(cmd: Cmd, reporter: Option<ProgressFn>, cancel: Option<&CancelToken>) -> Res<()> {
+    match cmd {
+        Cmd::Organize(items, target_path, policy)
+        | Cmd::AsyncOrganize(_, items, target_path, policy) => {
+            file_ops::organize(&items, &target_path, policy, reporter, cancel)
+        }
+        Cmd::Move(items, target_path, policy) | Cmd::AsyncMove(_, items, target_path, policy) => {
+            file_ops::move_into(&items, &target_path, policy, reporter, cancel)
+        }
+        Cmd::Copy(items, target_path, policy) | Cmd::AsyncCopy(_, items, target_path, policy) => {
+            file_ops::copy(&items, target_path, policy, reporter, cancel)
+        }
+        Cmd::Rename(pairs) | Cmd::AsyncRename(_, pairs) => file_ops::rename(&pairs),
+        Cmd::Delete(items) | Cmd::AsyncDelete(_, items) => file_ops::delete(&items),
+        Cmd::Trash(items) | Cmd::AsyncTrash(_, items) => file_ops::trash(&items),
+        Cmd::AsyncZip(_, items, target_path) => archive::archive(&items, &target_path, reporter),
+        Cmd::AsyncExtract(_, archive_path, target_path) => {
+            archive::extract(&archive_path, &target_path, reporter)
+        }
+        Cmd::Seq(cmds) | Cmd::Batch(cmds) => cmds
+            .into_iter()
+            .try_for_each(|cmd| execute(cmd, reporter.clone(), cancel)),
+        _ => Ok(()),
+    }
+}
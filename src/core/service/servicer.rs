@@ -34,6 +34,14 @@ impl Servicer {
         self.watcher = Watcher::new(self.msg_tx.clone(), watch_path).into();
     }
 
+    /// Make the watcher follow the shown directory, starting it if needed
+    pub fn change_watch_path(&mut self, watch_path: PathBuf) {
+        match &self.watcher {
+            Some(watcher) => watcher.change_path(watch_path),
+            None => self.set_watcher(watch_path),
+        }
+    }
+
     pub fn try_recv(&mut self) -> Result<Msg, tokio::sync::mpsc::error::TryRecvError> {
         self.rx.try_recv()
     }
@@ -1,14 +1,53 @@
-use std::sync::{Arc, atomic::AtomicU64};
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
 
 use dashmap::DashMap;
 use tokio::sync::{Semaphore, mpsc::Sender};
 
-use crate::core::msg::{Msg, TaskState, TaskStatus};
+use crate::core::msg::{Msg, Progress, TaskState, TaskStatus};
+
+/// 进度汇报：(已完成字节数, 总字节数)
+pub type ProgressFn = Arc<dyn Fn(u64, u64) + Send + Sync>;
+
+/// 两次进度消息之间的最短间隔，最后一次总会发出
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The error a task gives up with once it notices the [`CancelToken`]
+pub const CANCELLED: &str = "Cancelled";
+
+/// Whether a task error, causes joined by `: `, ends in [`CANCELLED`]
+fn is_cancelled_error(e: &str) -> bool {
+    e.rsplit(": ").next() == Some(CANCELLED)
+}
+
+/// 取消令牌，任务在合适的时机自行检查
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
 
 #[derive(Debug)]
 pub struct TaskManager {
     pub semaphore: Arc<tokio::sync::Semaphore>,
     pub registry: Arc<DashMap<u64, TaskStatus>>,
+    tokens: Arc<DashMap<u64, CancelToken>>,
     pub next_id: AtomicU64,
     pub status_tx: tokio::sync::mpsc::Sender<Msg>,
 }
@@ -18,6 +57,7 @@ impl TaskManager {
         Self {
             semaphore: Arc::new(Semaphore::new(permits)),
             registry: Arc::new(DashMap::new()),
+            tokens: Arc::new(DashMap::new()),
             next_id: AtomicU64::new(0),
             status_tx: tx,
         }
@@ -26,46 +66,77 @@ impl TaskManager {
     // 提交任务的方法
     pub fn submit<F>(&self, id: u64, epoch: u32, task_fn: F)
     where
-        F: FnOnce(Arc<dyn Fn(f32) + Send + Sync>) -> Result<(), String> + Send + 'static,
+        F: FnOnce(ProgressFn, CancelToken) -> Result<(), String> + Send + 'static,
     {
         // 初始化状态
         self.registry.insert(id, TaskStatus::Pending);
+        let token = CancelToken::new();
+        self.tokens.insert(id, token.clone());
+        let tokens_clone = Arc::clone(&self.tokens);
 
         let sem_clone = Arc::clone(&self.semaphore);
         let registry_clone = Arc::clone(&self.registry);
         let tx_clone = self.status_tx.clone();
 
-        // 创建进度汇报闭包
-        let registry_progress = Arc::clone(&self.registry);
-        let tx_progress = self.status_tx.clone();
-        let reporter = Arc::new(move |progress: f32| {
-            let status = TaskStatus::Processing(progress);
-            registry_progress.insert(id, status.clone());
-            // 在 blocking 线程中需使用 blocking_send
-            let _ = tx_progress.blocking_send(Msg::TaskState(TaskState::new(id, epoch, status)));
-        });
-
         // 开启异步包装器
         tokio::spawn(async move {
             // 等待并发许可
             let _permit = sem_clone.acquire().await.unwrap();
 
+            // 还没开始就被取消了
+            if token.is_cancelled() {
+                tokens_clone.remove(&id);
+                registry_clone.insert(id, TaskStatus::Cancelled);
+                let _ = tx_clone
+                    .send(Msg::TaskState(TaskState::new(
+                        id,
+                        epoch,
+                        TaskStatus::Cancelled,
+                    )))
+                    .await;
+                return;
+            }
+
             // 更新状态为处理中
-            registry_clone.insert(id, TaskStatus::Processing(0.0));
+            let status = TaskStatus::Processing(Progress::default());
+            registry_clone.insert(id, status.clone());
             let _ = tx_clone
-                .send(Msg::TaskState(TaskState::new(
-                    id,
-                    epoch,
-                    TaskStatus::Processing(0.0),
-                )))
+                .send(Msg::TaskState(TaskState::new(id, epoch, status)))
                 .await;
 
+            // 创建进度汇报闭包，吞吐量从这里开始算
+            let registry_progress = Arc::clone(&registry_clone);
+            let tx_progress = tx_clone.clone();
+            let started = Instant::now();
+            let last_sent = Mutex::new(None::<Instant>);
+            let reporter: ProgressFn = Arc::new(move |done: u64, total: u64| {
+                let now = Instant::now();
+                {
+                    let mut last_sent = last_sent.lock().unwrap();
+                    if done < total && last_sent.is_some_and(|last| now - last < PROGRESS_INTERVAL)
+                    {
+                        return;
+                    }
+                    *last_sent = Some(now);
+                }
+                let status = TaskStatus::Processing(Progress::new(done, total, now - started));
+                registry_progress.insert(id, status.clone());
+                // 在 blocking 线程中需使用 blocking_send
+                let _ =
+                    tx_progress.blocking_send(Msg::TaskState(TaskState::new(id, epoch, status)));
+            });
+
             // 在阻塞线程池中执行重型任务
-            let result = tokio::task::spawn_blocking(move || task_fn(reporter)).await;
+            let task_token = token.clone();
+            let result = tokio::task::spawn_blocking(move || task_fn(reporter, task_token)).await;
+            tokens_clone.remove(&id);
 
-            // 处理最终结果
+            // 处理最终结果，只有因取消而停下的才算作取消，其余错误照常报告
             let final_status = match result {
                 Ok(Ok(_)) => TaskStatus::Completed,
+                Ok(Err(e)) if token.is_cancelled() && is_cancelled_error(&e) => {
+                    TaskStatus::Cancelled
+                }
                 Ok(Err(e)) => TaskStatus::Failed(e),
                 Err(e) => TaskStatus::Failed(format!("Runtime Error: {}", e)),
             };
@@ -77,6 +148,17 @@ impl TaskManager {
         });
     }
 
+    /// Ask the task to stop, false if it is not running any more
+    pub fn cancel(&self, id: u64) -> bool {
+        match self.tokens.get(&id) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
     /// 清理已完成或失败的任务状态，防止内存泄漏
     pub fn prune_finished(&self) {
         self.registry
@@ -87,3 +169,96 @@ impl TaskManager {
         self.registry.get(&id).map(|r| r.value().clone())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Wait for the final status of task `id`
+    async fn finished(rx: &mut tokio::sync::mpsc::Receiver<Msg>, id: u64) -> TaskStatus {
+        while let Some(msg) = rx.recv().await {
+            if let Msg::TaskState(state) = msg
+                && state.id == id
+                && !matches!(
+                    state.status,
+                    TaskStatus::Pending | TaskStatus::Processing(_)
+                )
+            {
+                return state.status;
+            }
+        }
+        panic!("channel closed");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_cancel_running_task() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+        let manager = TaskManager::new(tx, 1);
+        let (started_tx, started_rx) = std::sync::mpsc::channel();
+
+        manager.submit(1, 0, move |_, cancel| {
+            started_tx.send(()).unwrap();
+            while !cancel.is_cancelled() {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+            Err(format!("Failed to copy 'a': {}", CANCELLED))
+        });
+        // finishes even though it is cancelled, too late
+        manager.submit(2, 0, |_, _| Ok(()));
+
+        tokio::task::spawn_blocking(move || started_rx.recv().unwrap())
+            .await
+            .unwrap();
+        assert!(manager.cancel(1));
+        assert_eq!(finished(&mut rx, 1).await, TaskStatus::Cancelled);
+        assert_eq!(finished(&mut rx, 2).await, TaskStatus::Completed);
+        assert!(!manager.cancel(1));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_cancel_pending_task() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+        let manager = TaskManager::new(tx, 1);
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let (started_tx, started_rx) = std::sync::mpsc::channel();
+
+        // 占住唯一的许可
+        manager.submit(1, 0, move |_, _| {
+            started_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
+            Ok(())
+        });
+        tokio::task::spawn_blocking(move || started_rx.recv().unwrap())
+            .await
+            .unwrap();
+        manager.submit(2, 0, |_, _| panic!("should not run"));
+        assert!(manager.cancel(2));
+        release_tx.send(()).unwrap();
+
+        assert_eq!(finished(&mut rx, 1).await, TaskStatus::Completed);
+        assert_eq!(finished(&mut rx, 2).await, TaskStatus::Cancelled);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_error_after_cancel_is_failure() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+        let manager = TaskManager::new(tx, 1);
+        let (started_tx, started_rx) = std::sync::mpsc::channel();
+
+        manager.submit(1, 0, move |_, cancel| {
+            started_tx.send(()).unwrap();
+            while !cancel.is_cancelled() {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+            Err("Permission denied".to_string())
+        });
+        tokio::task::spawn_blocking(move || started_rx.recv().unwrap())
+            .await
+            .unwrap();
+        assert!(manager.cancel(1));
+        assert_eq!(
+            finished(&mut rx, 1).await,
+            TaskStatus::Failed("Permission denied".to_string())
+        );
+    }
+}
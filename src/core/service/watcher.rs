@@ -76,4 +76,11 @@ impl Watcher {
 
         Self { task, cmd_tx }
     }
+
+    /// Watch `path` instead of the current one
+    pub fn change_path(&self, path: PathBuf) {
+        if let Err(e) = self.cmd_tx.try_send(WatchCommand::ChangeWatchPath(path)) {
+            tracing::error!("[Watcher] failed to send change path: {:?}", e);
+        }
+    }
 }
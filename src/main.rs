@@ -2,31 +2,96 @@ mod app;
 mod cli;
 mod core;
 
+use std::process::ExitCode;
+
 use clap::Parser as _;
 use color_eyre::eyre::Result as Res;
 
-use crate::{app::App, cli::Cli};
+use color_eyre::eyre::Context as _;
+
+use crate::{
+    app::App,
+    cli::{Cli, Command, PlanCommand},
+    core::config::{Config, ConfigStatus},
+};
 
 #[tokio::main]
-async fn main() -> Res<()> {
-    let _guard = log_init()?;
+async fn main() -> Res<ExitCode> {
+    let args = Cli::parse();
+    // undo 和 plan apply 不打开目录，要打开的路径不存在也没关系
+    let config_status = match &args.command {
+        Some(Command::Undo)
+        | Some(Command::Plan {
+            command: PlanCommand::Apply { .. },
+        }) => Config::load_global(args.config.as_deref())?,
+        _ => Config::load(args.config.as_deref(), args.open_path())?,
+    };
+    let _guard = log_init(&args, &config_status)?;
     tracing::info!("[main] program start...");
+    // 加载配置时日志还没初始化，现在补上
+    for path in config_status.sources() {
+        tracing::info!("loaded config {:?}", path);
+    }
 
-    let args = Cli::parse();
-    let mut app = App::new(args)?;
-    app.run().await?;
+    match &args.command {
+        Some(Command::Undo) => cli::undo(args.dry_run)?,
+        Some(Command::Plan { command }) => match command {
+            PlanCommand::Export { output, format } => {
+                cli::plan_export(config_status.config_ref(), output.as_deref(), *format)?
+            }
+            PlanCommand::Apply { file } => cli::plan_apply(
+                file,
+                config_status.config_ref().conflict_policy,
+                args.dry_run,
+            )?,
+        },
+        None => {
+            let mut app = App::new(args, config_status)?;
+            app.run().await?;
+            if app.failed() {
+                tracing::info!("[main] program ended with failures");
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+    }
 
     tracing::info!("[main] program ended");
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
 
-fn log_init() -> color_eyre::Result<tracing_appender::non_blocking::WorkerGuard> {
-    // 1. 创建一个非阻塞的文件写入器 (写入到 logs/app.log)
-    let file_appender = tracing_appender::rolling::hourly(get_log_dir("ef"), "app.log");
+fn log_init(
+    args: &Cli,
+    config_status: &ConfigStatus,
+) -> color_eyre::Result<tracing_appender::non_blocking::WorkerGuard> {
+    use tracing_appender::rolling::{RollingFileAppender, Rotation};
+
+    let directive = args.log_directive(|key| std::env::var(key).ok());
+    let filter = tracing_subscriber::EnvFilter::try_new(&directive)
+        .with_context(|| format!("Invalid log filter '{}'", directive))?;
+
+    // 1. 创建一个非阻塞的文件写入器：--log-file 指定的文件，或者 logs/app.log.<小时>
+    let file_appender = match &args.log_file {
+        Some(path) => {
+            let dir = path.parent().filter(|p| !p.as_os_str().is_empty());
+            let dir = dir.unwrap_or(std::path::Path::new("."));
+            let name = path
+                .file_name()
+                .ok_or_else(|| color_eyre::eyre::eyre!("Invalid log file '{}'", path.display()))?;
+            fs::create_dir_all(dir)?;
+            tracing_appender::rolling::never(dir, name)
+        }
+        // 只保留最近的 log_max_files 个，免得越积越多
+        None => RollingFileAppender::builder()
+            .rotation(Rotation::HOURLY)
+            .filename_prefix("app.log")
+            .max_log_files(config_status.config_ref().log_max_files)
+            .build(get_log_dir("ef"))?,
+    };
     let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
 
     // 2. 初始化订阅者，设置格式包含 线程ID、时间、日志级别
     tracing_subscriber::fmt()
+        .with_env_filter(filter)
         .with_writer(non_blocking)
         .with_thread_ids(true) // 关键：看到是哪个线程在说话
         .with_thread_names(true)
@@ -40,6 +105,13 @@ use directories::ProjectDirs;
 use std::fs;
 
 fn get_log_dir(app_name: &str) -> std::path::PathBuf {
+    // 和操作日志（journal）放在一起
+    if let Some(log_dir) = crate::core::config::state_dir().map(|dir| dir.join("logs"))
+        && fs::create_dir_all(&log_dir).is_ok()
+    {
+        return log_dir;
+    }
+
     // Qualifier 通常用你的域名反写，如 "com.github.user"
     if let Some(proj_dirs) = ProjectDirs::from("com", "David-G96", app_name) {
         // 对于 CLI 程序的日志，推荐使用 cache_dir 或 state_dir
@@ -53,4 +125,3 @@ fn get_log_dir(app_name: &str) -> std::path::PathBuf {
     // 备选方案：如果获取不到系统路径，退回到当前目录的隐藏文件夹
     std::path::PathBuf::from(".logs")
 }
-